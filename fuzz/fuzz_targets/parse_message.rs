@@ -0,0 +1,15 @@
+#![no_main]
+
+use bark_dns_resolver::Message;
+use libfuzzer_sys::fuzz_target;
+
+// Any input must be either decoded or rejected with an error, never panic, and
+// whatever is decoded must be decoded again once encoded back
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = Message::from_bytes(data) {
+        let encoded = msg.to_bytes();
+        if Message::from_bytes(&encoded).is_err() {
+            panic!("Couldn't decode a re-encoded message: {:?}", encoded);
+        }
+    }
+});
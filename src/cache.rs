@@ -0,0 +1,294 @@
+// Cache of the answers received from the name servers, so that repeated lookups
+// don't go to the network while their records are still valid. Answers are kept
+// per question, i.e. per (name, type, class), along with the time they were
+// received, and are handed out with their TTLs counted down accordingly. Once
+// the cache is full, the least recently used answer makes room for the new one.
+//
+// Negative answers, NXDOMAIN and NODATA, are cached too (see RFC 2308). They
+// are kept with the SOA record of their zone, which gives them a TTL and is
+// handed out again in the authority section. A name that doesn't exist doesn't
+// exist for any type, so NXDOMAIN answers are kept per name instead.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::domain_name::DomainName;
+use crate::msg::{Message, MessageBuilder, ResponseCode};
+use crate::resource_record::{Class, ResourceRecordHeader, ResponseData, Type};
+
+// The type is None for answers that apply to every type, i.e. NXDOMAIN
+type CacheKey = (DomainName, Option<Type>, Class);
+
+// A record of a cached answer. Its TTL is clamped before the record is stored.
+struct CachedRecord {
+    name: DomainName,
+    rr_class: Class,
+    ttl: u32,
+    data: ResponseData
+}
+
+struct CacheEntry {
+    response_code: ResponseCode,
+    // Flags of the original response, given back along with its records
+    authoritative: bool,
+    recursion_available: bool,
+    authentic_data: bool,
+    answers: Vec<CachedRecord>,
+    // SOA record of a negative answer, whose TTL is the one of the answer
+    soa: Option<CachedRecord>,
+    received: Instant,
+    // The answer expires along with the first of its records
+    expires: Instant,
+    // Position of the entry in the order of use
+    last_used: u64
+}
+
+pub(crate) struct Cache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    // Keys of the entries by their last use, least recent first
+    recently_used: BTreeMap<u64, CacheKey>,
+    uses: u64,
+    max_entries: usize,
+    min_ttl: u32,
+    max_ttl: u32
+}
+
+impl Cache {
+    /// Creates a cache holding up to `max_entries` answers, 0 meaning nothing is
+    /// cached. TTLs are raised to `min_ttl` and lowered to `max_ttl`, the latter
+    /// taking precedence.
+    pub(crate) fn new(max_entries: usize, min_ttl: Duration, max_ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recently_used: BTreeMap::new(),
+            uses: 0,
+            max_entries,
+            min_ttl: ttl_seconds(min_ttl),
+            max_ttl: ttl_seconds(max_ttl)
+        }
+    }
+
+    /// Returns a response to `query` made of the cached answer to its question,
+    /// None if there is none or it has expired.
+    pub(crate) fn get(&mut self, query: &Message, now: Instant) -> Option<Message> {
+        let (name, qtype, qclass) = cache_key(query)?;
+
+        let key = [(name.clone(), qtype, qclass), (name, None, qclass)].into_iter()
+            .find(|key| self.entries.contains_key(key))?;
+
+        let expires = self.entries.get(&key)?.expires;
+        if expires <= now {
+            self.remove(&key);
+            return None;
+        }
+
+        self.uses += 1;
+        let entry = self.entries.get_mut(&key)?;
+        self.recently_used.remove(&entry.last_used);
+        self.recently_used.insert(self.uses, key);
+        entry.last_used = self.uses;
+
+        let elapsed = ttl_seconds(now.duration_since(entry.received));
+        let ttl = |record: &CachedRecord| record.ttl.saturating_sub(elapsed);
+
+        let mut response = MessageBuilder::response_to(query)
+            .with_authoritative(entry.authoritative)
+            .with_recursion_available(entry.recursion_available)
+            .with_authentic_data(entry.authentic_data)
+            .with_response_code(entry.response_code);
+        for record in entry.answers.iter() {
+            response = response.with_answer(record.name.clone(), record.rr_class, ttl(record), record.data.clone());
+        }
+        if let Some(soa) = &entry.soa {
+            response = response.with_authority(soa.name.clone(), soa.rr_class, ttl(soa), soa.data.clone());
+        }
+
+        // The records come from a decoded message, so they always fit in one again
+        response.build().ok()
+    }
+
+    /// Keeps `response` as the answer to the question of `query`. Only complete
+    /// answers are cached, either successful or telling that the name or records
+    /// don't exist, and the latter only along with an SOA record.
+    pub(crate) fn insert(&mut self, query: &Message, response: &Message, now: Instant) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let (qname, qtype, qclass) = match cache_key(query) {
+            Some(key) => key,
+            None => return
+        };
+
+        let response_code = response.response_code();
+        if !matches!(response_code, ResponseCode::NoError | ResponseCode::NameError)
+            || response.header().truncation() {
+            return;
+        }
+
+        let mut answers: Vec<CachedRecord> = response.answers().iter()
+            .map(|rr| cached_record(rr.header(), rr.header().ttl(), rr.data()))
+            .collect();
+
+        // Negative answers without an SOA record must not be cached (see RFC 2308, section 5)
+        let mut soa = negative_soa(response, &qname);
+        let negative = response_code == ResponseCode::NameError || answers.is_empty();
+        if negative && soa.is_none() {
+            return;
+        }
+
+        // Records with a TTL of 0 must not be cached at all (see RFC 1035, section 3.2.1),
+        // so this is checked before the minimum TTL is applied
+        if answers.iter().chain(soa.iter()).any(|record| record.ttl == 0) {
+            return;
+        }
+
+        for record in answers.iter_mut().chain(soa.iter_mut()) {
+            record.ttl = record.ttl.max(self.min_ttl).min(self.max_ttl);
+        }
+
+        let ttl = match answers.iter().chain(soa.iter()).map(|record| record.ttl).min() {
+            Some(ttl) if ttl > 0 => ttl,
+            _ => return
+        };
+
+        // Without answers, NXDOMAIN is about the name asked for. Otherwise it is
+        // about the end of a CNAME chain, and only this question can be answered.
+        let key = if response_code == ResponseCode::NameError && answers.is_empty() {
+            (qname, None, qclass)
+        } else {
+            (qname, qtype, qclass)
+        };
+
+        self.remove(&key);
+        while self.entries.len() >= self.max_entries {
+            match self.recently_used.pop_first() {
+                Some((_, least_recent)) => self.entries.remove(&least_recent),
+                None => break
+            };
+        }
+
+        self.uses += 1;
+        self.recently_used.insert(self.uses, key.clone());
+        let header = response.header();
+        self.entries.insert(key, CacheEntry {
+            response_code,
+            authoritative: header.authoritative(),
+            recursion_available: header.recursion_available(),
+            authentic_data: header.authentic_data(),
+            answers,
+            soa,
+            received: now,
+            expires: now + Duration::from_secs(ttl as u64),
+            last_used: self.uses
+        });
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recently_used.remove(&entry.last_used);
+        }
+    }
+}
+
+// Returns the SOA record of the authority section that belongs to the zone of
+// `qname`, or of the name its CNAME chain ends at. Its TTL is the one of negative
+// answers: the lower of the TTL of the record and its MINIMUM field (see RFC 2308,
+// section 5).
+fn negative_soa(response: &Message, qname: &DomainName) -> Option<CachedRecord> {
+    let name = response.canonical_name(qname);
+
+    response.authorities().iter()
+        .filter(|rr| name.is_subdomain_of(rr.header().name()))
+        .find_map(|rr| match rr.data() {
+            ResponseData::SOA(soa) => {
+                let ttl = rr.header().ttl().min(soa.minimum());
+                Some(cached_record(rr.header(), ttl, ResponseData::SOA(soa)))
+            },
+            _ => None
+        })
+}
+
+fn cached_record(header: &ResourceRecordHeader, ttl: u32, data: ResponseData) -> CachedRecord {
+    CachedRecord {
+        name: header.name().clone(),
+        rr_class: header.rr_class(),
+        ttl,
+        data
+    }
+}
+
+// Only queries with a single question are cached, as they are the only ones
+// name servers answer in practice
+fn cache_key(query: &Message) -> Option<CacheKey> {
+    match query.questions() {
+        [question] => Some((question.qname().clone(), Some(question.qtype()), question.qclass())),
+        _ => None
+    }
+}
+
+fn ttl_seconds(duration: Duration) -> u32 {
+    u32::try_from(duration.as_secs()).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn query() -> Message {
+        Message::query("www.example.com.".parse().unwrap(), Type::A, Class::Internet)
+    }
+
+    fn answer(query: &Message, ttl: u32) -> MessageBuilder {
+        MessageBuilder::response_to(query)
+            .with_answer("www.example.com.".parse().unwrap(), Class::Internet, ttl,
+                         ResponseData::A(Ipv4Addr::new(192, 0, 2, 1)))
+    }
+
+    #[test]
+    fn zero_ttl_is_not_cached_whatever_the_minimum_ttl() {
+        let mut cache = Cache::new(10, Duration::from_secs(60), Duration::from_secs(3600));
+        let query = query();
+        let now = Instant::now();
+
+        cache.insert(&query, &answer(&query, 0).build().unwrap(), now);
+
+        assert!(cache.get(&query, now).is_none());
+    }
+
+    #[test]
+    fn ttls_are_clamped_and_counted_down() {
+        let mut cache = Cache::new(10, Duration::from_secs(60), Duration::from_secs(3600));
+        let query = query();
+        let now = Instant::now();
+
+        cache.insert(&query, &answer(&query, 30).build().unwrap(), now);
+
+        let cached = cache.get(&query, now + Duration::from_secs(10)).unwrap();
+        assert_eq!(cached.answers()[0].header().ttl(), 50);
+        assert!(cache.get(&query, now + Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn keeps_the_flags_of_the_response() {
+        let mut cache = Cache::new(10, Duration::ZERO, Duration::from_secs(3600));
+        let query = query();
+        let now = Instant::now();
+
+        for (authoritative, recursion_available, authentic_data) in [(true, false, true), (false, true, false)] {
+            let response = answer(&query, 300)
+                .with_authoritative(authoritative)
+                .with_recursion_available(recursion_available)
+                .with_authentic_data(authentic_data)
+                .build().unwrap();
+            cache.insert(&query, &response, now);
+
+            let cached = cache.get(&query, now).unwrap();
+            assert_eq!(cached.header().authoritative(), authoritative);
+            assert_eq!(cached.header().recursion_available(), recursion_available);
+            assert_eq!(cached.header().authentic_data(), authentic_data);
+        }
+    }
+}
@@ -1,27 +1,349 @@
-use regex::Regex;
-use crate::serialize::{Deserialize, DeserializationError, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::{error, fmt};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
-#[derive(Debug)]
-pub(crate) struct DomainName(String);
+use idna::AsciiDenyList;
+
+use crate::serialize::{Deserialize, DeserializationError, read_slice, read_u16, read_u8, Serialize};
+
+// The two upper bits of a pointer, which tell it apart from a label length
+const COMPRESSION_FLAGS: u16 = 0xC000;
+// Pointers only have 14 bits for the offset
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+// Including the length octets and the final 0 (see RFC 1035, section 2.3.4)
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_LABEL_LENGTH: usize = 63;
+// A name of 255 octets has at most 127 labels, so a valid message never needs
+// more pointers than that to spell one
+const MAX_POINTER_HOPS: usize = 127;
+// Labels starting with it are the ASCII form of an internationalized label
+const ACE_PREFIX: &str = "xn--";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DomainNameError {
+    // Two dots in a row, or a dot at the start of a name other than the root
+    EmptyLabel,
+    // Labels can't be longer than 63 octets
+    LabelTooLong(usize),
+    // Names can't be longer than 255 octets on the wire
+    NameTooLong(usize),
+    // A backslash at the end of the name, or a \DDD escape above 255
+    InvalidEscape,
+    // A name with non-ASCII characters that can't be converted to ASCII form
+    InvalidIdna
+}
+
+impl fmt::Display for DomainNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DomainNameError::EmptyLabel => write!(f, "empty label"),
+            DomainNameError::LabelTooLong(length) =>
+                write!(f, "label of {} octets, longer than {}", length, MAX_LABEL_LENGTH),
+            DomainNameError::NameTooLong(length) =>
+                write!(f, "name of {} octets, longer than {}", length, MAX_NAME_LENGTH),
+            DomainNameError::InvalidEscape => write!(f, "invalid escape sequence"),
+            DomainNameError::InvalidIdna => write!(f, "invalid internationalized name")
+        }
+    }
+}
+
+impl error::Error for DomainNameError {}
+
+/// A domain name, as a sequence of labels holding raw bytes. Labels are
+/// usually ASCII, but nothing in DNS requires them to be.
+///
+/// Names are compared ignoring ASCII case, as DNS does, and are ordered as
+/// required by DNSSEC (see RFC 4034, section 6.1). Whether a name is fully
+/// qualified (written with a trailing dot) is kept for presentation only: it
+/// doesn't change its encoding nor how it compares.
+#[derive(Clone, Debug)]
+pub struct DomainName {
+    labels: Vec<Vec<u8>>,
+    fqdn: bool
+}
 
 impl DomainName {
-    pub fn from_string(domain_name: &str) -> Self {
-        //Self::is_valid(domain_name.to_string());
+    /// The root name, "."
+    pub fn root() -> Self {
+        Self {
+            labels: vec![],
+            fqdn: true
+        }
+    }
+
+    /// Builds a fully qualified name from its labels, the top-level one last
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<Self, DomainNameError> {
+        let name = Self {
+            labels,
+            fqdn: true
+        };
+        name.check_lengths()?;
+
+        Ok(name)
+    }
+
+    pub fn labels(&self) -> &[Vec<u8>] {
+        &self.labels
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn is_fqdn(&self) -> bool {
+        self.fqdn
+    }
+
+    pub fn to_fqdn(&self) -> Self {
+        Self {
+            labels: self.labels.clone(),
+            fqdn: true
+        }
+    }
+
+    pub fn to_relative(&self) -> Self {
+        Self {
+            labels: self.labels.clone(),
+            fqdn: false
+        }
+    }
+
+    /// The name without its first label, None for the root
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+
+        Some(Self {
+            labels: self.labels[1..].to_vec(),
+            fqdn: self.fqdn
+        })
+    }
+
+    /// Whether this name is `other` or a name below it, e.g. "www.example.com"
+    /// is a subdomain of both "example.com" and "www.example.com"
+    pub fn is_subdomain_of(&self, other: &DomainName) -> bool {
+        if other.labels.len() > self.labels.len() {
+            return false;
+        }
 
-        Self(domain_name.to_string())
+        self.labels.iter().rev()
+            .zip(other.labels.iter().rev())
+            .all(|(label, other_label)| label.eq_ignore_ascii_case(other_label))
     }
 
-    fn is_valid(domain_name: String) -> bool {
-        // I literally copied this regex from stackoverflow
-        let reg = Regex::new(
-            r"^(([a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9\-]*[a-zA-Z0-9])\.)*([A-Za-z0-9]|[A-Za-z0-9][A-Za-z0-9\-]*[A-Za-z0-9])$")
-            .unwrap();
+    /// Appends `other` to this name, e.g. "www" and "example.com." make
+    /// "www.example.com.". The result is fully qualified if `other` is.
+    pub fn append(&self, other: &DomainName) -> Result<Self, DomainNameError> {
+        let name = Self {
+            labels: [self.labels.as_slice(), other.labels.as_slice()].concat(),
+            fqdn: other.fqdn
+        };
+        name.check_lengths()?;
 
-        if !reg.is_match(&domain_name) {
-            panic!("Invalid domain name");
+        Ok(name)
+    }
+
+    fn check_lengths(&self) -> Result<(), DomainNameError> {
+        for label in self.labels.iter() {
+            if label.is_empty() {
+                return Err(DomainNameError::EmptyLabel);
+            }
+            if label.len() > MAX_LABEL_LENGTH {
+                return Err(DomainNameError::LabelTooLong(label.len()));
+            }
+        }
+
+        let length = self.wire_length();
+        if length > MAX_NAME_LENGTH {
+            return Err(DomainNameError::NameTooLong(length));
+        }
+
+        Ok(())
+    }
+
+    // Every label takes its length octet plus its bytes, and then the final 0
+    fn wire_length(&self) -> usize {
+        self.labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+    }
+}
+
+// Parses names in presentation format, where a '.' or '\' inside a label is
+// escaped with a '\', and any byte may be written as \DDD (in decimal)
+impl FromStr for DomainName {
+    type Err = DomainNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.is_ascii() {
+            return Self::from_unicode(s);
+        }
+
+        if s == "." {
+            return Ok(Self::root());
+        }
+
+        let mut labels = vec![];
+        let mut label = vec![];
+        let mut fqdn = false;
+
+        let mut bytes = s.bytes();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'.' => {
+                    if label.is_empty() {
+                        return Err(DomainNameError::EmptyLabel);
+                    }
+                    labels.push(std::mem::take(&mut label));
+                    // Only a trailing dot makes the name fully qualified
+                    fqdn = bytes.len() == 0;
+                },
+                b'\\' => {
+                    let escaped = bytes.next().ok_or(DomainNameError::InvalidEscape)?;
+                    if !escaped.is_ascii_digit() {
+                        label.push(escaped);
+                        continue;
+                    }
+
+                    let digits = [Some(escaped), bytes.next(), bytes.next()];
+                    let mut value = 0u16;
+                    for digit in digits {
+                        match digit {
+                            Some(digit) if digit.is_ascii_digit() => value = value * 10 + (digit - b'0') as u16,
+                            _ => return Err(DomainNameError::InvalidEscape)
+                        }
+                    }
+                    label.push(u8::try_from(value).map_err(|_| DomainNameError::InvalidEscape)?);
+                },
+                _ => label.push(byte)
+            }
         }
 
-        true
+        if !fqdn {
+            if label.is_empty() {
+                return Err(DomainNameError::EmptyLabel);
+            }
+            labels.push(label);
+        }
+
+        let name = Self {
+            labels,
+            fqdn
+        };
+        name.check_lengths()?;
+
+        Ok(name)
+    }
+}
+
+impl DomainName {
+    /// Builds a name from its Unicode form, e.g. "münchen.de", converting every
+    /// label to its ASCII form ("xn--mnchen-3ya.de") as defined by UTS #46.
+    /// Parsing a name with non-ASCII characters does this as well.
+    pub fn from_unicode(name: &str) -> Result<Self, DomainNameError> {
+        let ascii = idna::domain_to_ascii_cow(name.as_bytes(), AsciiDenyList::EMPTY)
+            .map_err(|_| DomainNameError::InvalidIdna)?;
+
+        ascii.parse()
+    }
+
+    /// The name in presentation format, like its Display, but with the labels in
+    /// ASCII form ("xn--...") written back in Unicode
+    pub fn to_unicode(&self) -> String {
+        let mut name = String::new();
+        // Writing to a String never fails
+        let _ = self.write_name(&mut name, true);
+
+        name
+    }
+
+    // Escapes what wouldn't be read back as the same label
+    fn write_name(&self, f: &mut impl fmt::Write, unicode: bool) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+
+            if unicode {
+                if let Some(label) = unicode_label(label) {
+                    write!(f, "{}", label)?;
+                    continue;
+                }
+            }
+
+            for byte in label {
+                match byte {
+                    b'.' | b'\\' => write!(f, "\\{}", *byte as char)?,
+                    0x21..=0x7E => write!(f, "{}", *byte as char)?,
+                    _ => write!(f, "\\{:03}", byte)?
+                }
+            }
+        }
+
+        if self.fqdn {
+            write!(f, ".")?;
+        }
+
+        Ok(())
+    }
+}
+
+// The Unicode form of `label` if it is a valid A-label, None otherwise
+fn unicode_label(label: &[u8]) -> Option<String> {
+    let label = std::str::from_utf8(label).ok()?;
+    if !label.get(..ACE_PREFIX.len())?.eq_ignore_ascii_case(ACE_PREFIX) {
+        return None;
+    }
+
+    match idna::domain_to_unicode(label) {
+        (label, Ok(())) => Some(label),
+        (_, Err(_)) => None
+    }
+}
+
+// Presentation format
+impl fmt::Display for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_name(f, false)
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels.len() == other.labels.len() && self.is_subdomain_of(other)
+    }
+}
+
+impl Eq for DomainName {}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for label in self.labels.iter() {
+            label.to_ascii_lowercase().hash(state);
+        }
+    }
+}
+
+// Canonical order (see RFC 4034, section 6.1): labels are compared from the
+// top-level one down, as lowercase byte strings, and a name sorts before the
+// names below it
+impl Ord for DomainName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let labels = self.labels.iter().rev().map(|label| label.to_ascii_lowercase());
+        let other_labels = other.labels.iter().rev().map(|label| label.to_ascii_lowercase());
+
+        labels.cmp(other_labels)
+    }
+}
+
+impl PartialOrd for DomainName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -30,10 +352,9 @@ impl Serialize for DomainName {
     fn serialize(&self) -> Vec<u8> {
         let mut bytes = vec![];
 
-        for label in self.0.split('.') {
-            let label_length = label.len() as u8;
-            bytes.push(label_length);
-            bytes.extend_from_slice(label.as_bytes());
+        for label in self.labels.iter() {
+            bytes.push(label.len() as u8);
+            bytes.extend_from_slice(label);
         }
 
         bytes.push(0);
@@ -42,63 +363,118 @@ impl Serialize for DomainName {
     }
 }
 
+/// Remembers where names have been written in a message, so that a name whose
+/// suffix was already written can point to it instead of repeating its labels
+/// (see RFC 1035, section 4.1.4). It is public only because record types take it
+/// when encoding; it isn't exported from the crate.
+pub struct NameCompressor {
+    // Offset of every suffix written so far, e.g. "www.example.com",
+    // "example.com" and "com" for a single name
+    offsets: HashMap<Vec<Vec<u8>>, u16>
+}
+
+impl NameCompressor {
+    pub(crate) fn new() -> Self {
+        Self {
+            offsets: HashMap::new()
+        }
+    }
+
+    /// Appends `name` to `bytes`, which must hold the message from its first byte
+    pub(crate) fn serialize_name(&mut self, name: &DomainName, bytes: &mut Vec<u8>) {
+        let labels = name.labels();
+
+        for i in 0..labels.len() {
+            // Suffixes are matched exactly rather than case-insensitively, so
+            // that names keep the case they were given
+            let suffix = &labels[i..];
+            if let Some(offset) = self.offsets.get(suffix) {
+                bytes.extend_from_slice(&(COMPRESSION_FLAGS | offset).to_be_bytes());
+                return;
+            }
+
+            if bytes.len() <= MAX_POINTER_OFFSET {
+                self.offsets.insert(suffix.to_vec(), bytes.len() as u16);
+            }
+
+            bytes.push(labels[i].len() as u8);
+            bytes.extend_from_slice(&labels[i]);
+        }
+
+        bytes.push(0);
+    }
+}
+
 impl Deserialize for DomainName {
+    // Names are decoded defensively, since they come from the network: every
+    // pointer must go backwards, so a pointer can never lead to itself, and there
+    // is a fixed limit on how many of them are followed for a single name
     fn deserialize(bytes: &[u8], offset: usize)
         -> Result<(usize, Self), DeserializationError>
     where
         Self: Sized
     {
-        let mut labels_vec = Vec::new();
+        let mut labels = Vec::new();
         let mut idx = offset;
-        let mut is_compression_byte = false;
-        let mut read_bytes = 0;
-        let mut nested = false;
-
-        while bytes[idx] != 0 {
-            // Check if compression is being used. This raw comparison is
-            // safe since labels are restricted to 63 octets or less
-            // (see RFC 1035).
-            is_compression_byte = (bytes[idx] >> 6) == 0b00000011;
-
-            if is_compression_byte  {
-                // 0x3FFF discards the first two bits of the word, since these are not
-                // necessary because they only indicate that the word is a pointer
-                idx = (u16::from_be_bytes([bytes[idx], bytes[idx + 1]]) & 0x3FFF) as usize;
-
-                if !nested {
-                    read_bytes += 2;
-                    nested = true;
-                }
-                continue;
+        // Bytes read at `offset`, known once the first pointer or the final 0 is found
+        let mut read_bytes = None;
+        let mut pointer_hops = 0;
+        // Length of the name on the wire, without compression
+        let mut name_length = 1;
+
+        loop {
+            let (_, length_byte) = read_u8(bytes, idx)?;
+
+            match length_byte >> 6 {
+                0b00 => {},
+                0b11 => {
+                    let (_, pointer) = read_u16(bytes, idx)?;
+                    let target = (pointer & !COMPRESSION_FLAGS) as usize;
+
+                    if target >= idx {
+                        // Only pointers to earlier bytes are allowed
+                        return Err(DeserializationError::invalid_data(idx, "compression pointer", target as u64))
+                    }
+
+                    pointer_hops += 1;
+                    if pointer_hops > MAX_POINTER_HOPS {
+                        return Err(DeserializationError::invalid_data(
+                            offset, "number of compression pointers", pointer_hops as u64))
+                    }
+
+                    read_bytes.get_or_insert_with(|| idx + 2 - offset);
+                    idx = target;
+                    continue;
+                },
+                // 0b01 and 0b10 are reserved (see RFC 6891, section 5)
+                _ => return Err(DeserializationError::invalid_data(idx, "label type", (length_byte >> 6).into()))
             }
 
-            let label_length = bytes[idx] as usize;
+            let label_length = length_byte as usize;
             idx += 1;
 
             if label_length == 0 {
+                read_bytes.get_or_insert_with(|| idx - offset);
                 break;
             }
 
-            let label =
-                String::from_utf8(bytes[idx..idx + label_length].to_vec()).unwrap();
-            labels_vec.push(label);
-
-            idx += label_length;
-            if !nested {
-                read_bytes += label_length + 1;
+            name_length += label_length + 1;
+            if name_length > MAX_NAME_LENGTH {
+                return Err(DeserializationError::invalid_data(offset, "name length", name_length as u64))
             }
-        }
-
-        // Also count the 0 byte
-        if !nested {
-            read_bytes += 1;
-        }
 
-        let labels = labels_vec.join(".");
+            let label = read_slice(bytes, idx, label_length)?;
+            labels.push(label.to_vec());
 
-        let dn = DomainName::from_string(&labels);
+            idx += label_length;
+        }
 
-        Ok((read_bytes, dn))
+        // Names on the wire always end at the root
+        let dn = DomainName {
+            labels,
+            fqdn: true
+        };
 
+        Ok((read_bytes.unwrap_or_default(), dn))
     }
-}
\ No newline at end of file
+}
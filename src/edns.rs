@@ -0,0 +1,217 @@
+// EDNS(0) support (see RFC 6891). The OPT pseudo-record travels in the additional
+// section, but it describes the message rather than any name: its CLASS field holds
+// the UDP payload size the sender can receive, and its TTL field holds the upper
+// bits of the response code, the EDNS version and the DO flag. That is why it gets
+// its own type instead of being a ResourceRecord.
+
+use crate::domain_name::DomainName;
+use crate::resource_record::Type;
+use crate::serialize::{Deserialize, DeserializationError, read_slice, read_u16, read_u32, Serialize};
+
+// Payload size advertised by default. It avoids IP fragmentation on virtually any
+// path, as recommended by the DNS flag day 2020.
+pub(crate) const DEFAULT_EDNS_PAYLOAD_SIZE: u16 = 1232;
+// Senders must not advertise less than this (see RFC 6891, section 6.2.5)
+const MIN_EDNS_PAYLOAD_SIZE: u16 = 512;
+const EDNS_VERSION: u8 = 0;
+const DO_FLAG_SHIFT: usize = 15;
+// The flags other than DO, which have no meaning yet but must be kept as they are
+const Z_FLAGS_MASK: u16 = 0x7FFF;
+const EXTENDED_RCODE_SHIFT: usize = 4;
+
+/// An EDNS option, kept as the raw code and data found on the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdnsOption {
+    code: u16,
+    data: Vec<u8>
+}
+
+impl EdnsOption {
+    pub fn new(code: u16, data: Vec<u8>) -> Self {
+        Self {
+            code,
+            data
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The EDNS(0) parameters of a message, carried by its OPT pseudo-record.
+#[derive(Clone, Debug)]
+pub struct Edns {
+    udp_payload_size: u16,
+    // Upper 8 bits of the 12-bit extended response code
+    extended_rcode: u8,
+    version: u8,
+    dnssec_ok: bool,
+    z: u16,
+    options: Vec<EdnsOption>
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Self {
+        Self {
+            udp_payload_size: udp_payload_size.max(MIN_EDNS_PAYLOAD_SIZE),
+            extended_rcode: 0,
+            version: EDNS_VERSION,
+            dnssec_ok: false,
+            z: 0,
+            options: vec![]
+        }
+    }
+
+    /// Size of the largest UDP message the sender can receive
+    pub fn udp_payload_size(&self) -> u16 {
+        self.udp_payload_size
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the sender wants DNSSEC records in the response (see RFC 3225)
+    pub fn dnssec_ok(&self) -> bool {
+        self.dnssec_ok
+    }
+
+    /// The 15 flag bits other than DO, reserved for future use
+    pub fn z(&self) -> u16 {
+        self.z
+    }
+
+    pub fn options(&self) -> &[EdnsOption] {
+        &self.options
+    }
+
+    pub fn set_version(&mut self, version: u8) {
+        self.version = version;
+    }
+
+    pub fn set_dnssec_ok(&mut self, dnssec_ok: bool) {
+        self.dnssec_ok = dnssec_ok;
+    }
+
+    pub fn add_option(&mut self, option: EdnsOption) {
+        self.options.push(option);
+    }
+
+    /// Whether the resource record starting at `offset` is an OPT pseudo-record
+    pub(crate) fn is_opt_record(bytes: &[u8], offset: usize) -> Result<bool, DeserializationError> {
+        let (off, _) = DomainName::deserialize(bytes, offset)?;
+        let (_, rr_type) = read_u16(bytes, offset + off)?;
+
+        Ok(Type::from(rr_type) == Type::OPT)
+    }
+
+    // Length of the RDATA of the OPT record, i.e. of its options
+    pub(crate) fn rdata_len(&self) -> usize {
+        self.options.iter().map(|option| 4 + option.data.len()).sum()
+    }
+
+    fn flags(&self) -> u32 {
+        let dnssec_ok = if self.dnssec_ok { 1 } else { 0 };
+
+        (self.extended_rcode as u32) << 24 | (self.version as u32) << 16 | dnssec_ok << DO_FLAG_SHIFT
+            | (self.z & Z_FLAGS_MASK) as u32
+    }
+
+    // Keeps the upper 8 bits of a 12-bit response code, the rest go in the header
+    pub(crate) fn set_extended_response_code(&mut self, response_code: u16) {
+        self.extended_rcode = (response_code >> EXTENDED_RCODE_SHIFT) as u8;
+    }
+
+    /// Combines the 4 bits of response code from the message header with the
+    /// upper 8 bits carried by this record.
+    pub(crate) fn full_response_code(&self, header_rcode: u8) -> u16 {
+        (self.extended_rcode as u16) << EXTENDED_RCODE_SHIFT | (header_rcode & 0x0F) as u16
+    }
+}
+
+impl Serialize for Edns {
+    fn serialize(&self) -> Vec<u8> {
+        // Messages are only encoded once their OPT RDATA is known to fit in RDLENGTH,
+        // see Edns::rdata_len
+        let mut rdata = vec![];
+        for option in self.options.iter() {
+            rdata.extend_from_slice(&option.code.to_be_bytes());
+            rdata.extend_from_slice(&(option.data.len() as u16).to_be_bytes());
+            rdata.extend_from_slice(&option.data);
+        }
+
+        // The owner name is always the root, i.e. a single 0 byte
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&u16::from(Type::OPT).to_be_bytes());
+        bytes.extend_from_slice(&self.udp_payload_size.to_be_bytes());
+        bytes.extend_from_slice(&self.flags().to_be_bytes());
+        bytes.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&rdata);
+
+        bytes
+    }
+}
+
+impl Deserialize for Edns {
+    fn deserialize(bytes: &[u8], offset: usize) -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, _) = DomainName::deserialize(bytes, offset)?;
+
+        // Type, which is already known to be OPT
+        let (off, _) = read_u16(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let (off, udp_payload_size) = read_u16(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let (off, flags) = read_u32(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let rdlength_offset = offset + read_bytes;
+        let (off, rdlength) = read_u16(bytes, rdlength_offset)?;
+        read_bytes += off;
+
+        let rdata_end = offset + read_bytes + rdlength as usize;
+        read_slice(bytes, offset + read_bytes, rdlength as usize)?;
+
+        let mut options = vec![];
+        while offset + read_bytes < rdata_end {
+            let (off, code) = read_u16(bytes, offset + read_bytes)?;
+            read_bytes += off;
+
+            let (off, length) = read_u16(bytes, offset + read_bytes)?;
+            read_bytes += off;
+
+            if offset + read_bytes + length as usize > rdata_end {
+                // The option would overflow the OPT record
+                return Err(DeserializationError::invalid_data(
+                    offset + read_bytes - off, "EDNS option length", length.into()))
+            }
+
+            let data = read_slice(bytes, offset + read_bytes, length as usize)?;
+            options.push(EdnsOption::new(code, data.to_vec()));
+            read_bytes += length as usize;
+        }
+
+        if offset + read_bytes != rdata_end {
+            // The options don't fill the RDATA exactly
+            return Err(DeserializationError::invalid_data(rdlength_offset, "OPT RDLENGTH", rdlength.into()))
+        }
+
+        Ok((read_bytes, Self {
+            udp_payload_size,
+            extended_rcode: (flags >> 24) as u8,
+            version: (flags >> 16) as u8,
+            dnssec_ok: (flags >> DO_FLAG_SHIFT) & 1 == 1,
+            z: flags as u16 & Z_FLAGS_MASK,
+            options
+        }))
+    }
+}
@@ -0,0 +1,123 @@
+// Lookups in a hosts(5) file, which is consulted before asking any name server.
+// The file is parsed lazily and parsed again whenever its modification time
+// changes, so edits are picked up without rebuilding the resolver.
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[derive(Default)]
+struct HostsEntries {
+    // Modification time of the file when it was parsed, None if it couldn't be read
+    modified: Option<SystemTime>,
+    // Lowercased names (canonical and aliases) to the addresses they map to
+    addresses: HashMap<String, Vec<IpAddr>>,
+    // Addresses to their names, canonical name first
+    names: HashMap<IpAddr, Vec<String>>
+}
+
+impl HostsEntries {
+    fn parse(contents: &str, modified: Option<SystemTime>) -> Self {
+        let mut entries = Self {
+            modified,
+            ..Default::default()
+        };
+
+        for line in contents.lines() {
+            let line = match line.find('#') {
+                Some(idx) => &line[..idx],
+                None => line
+            };
+
+            let mut words = line.split_whitespace();
+            let address = match words.next().and_then(parse_address) {
+                Some(address) => address,
+                None => continue
+            };
+
+            for name in words {
+                let name = name.trim_end_matches('.');
+
+                let addresses = entries.addresses.entry(name.to_ascii_lowercase()).or_default();
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+
+                let names = entries.names.entry(address).or_default();
+                if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        entries
+    }
+}
+
+pub(crate) struct Hosts {
+    path: PathBuf,
+    entries: Mutex<HostsEntries>
+}
+
+impl Hosts {
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            entries: Mutex::new(HostsEntries::default())
+        }
+    }
+
+    /// Returns every address `name` maps to, in file order.
+    pub(crate) fn lookup(&self, name: &str) -> Vec<IpAddr> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+
+        self.with_entries(|entries| entries.addresses.get(&name).cloned().unwrap_or_default())
+    }
+
+    pub(crate) fn lookup_ipv4(&self, name: &str) -> Vec<Ipv4Addr> {
+        self.lookup(name).into_iter()
+            .filter_map(|address| match address {
+                IpAddr::V4(ip) => Some(ip),
+                IpAddr::V6(_) => None
+            })
+            .collect()
+    }
+
+    pub(crate) fn lookup_ipv6(&self, name: &str) -> Vec<Ipv6Addr> {
+        self.lookup(name).into_iter()
+            .filter_map(|address| match address {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(ip) => Some(ip)
+            })
+            .collect()
+    }
+
+    /// Returns the names given to `address`, canonical name first.
+    pub(crate) fn reverse_lookup(&self, address: IpAddr) -> Vec<String> {
+        self.with_entries(|entries| entries.names.get(&address).cloned().unwrap_or_default())
+    }
+
+    fn with_entries<T>(&self, f: impl FnOnce(&HostsEntries) -> T) -> T {
+        // A poisoned lock only means another lookup panicked while reloading; the
+        // entries are replaced as a whole, so they are still consistent.
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified != entries.modified {
+            let contents = fs::read_to_string(&self.path).unwrap_or_default();
+            *entries = HostsEntries::parse(&contents, modified);
+        }
+
+        f(&entries)
+    }
+}
+
+fn parse_address(address: &str) -> Option<IpAddr> {
+    // Drop the zone of link-local IPv6 addresses ("fe80::1%eth0")
+    let address = address.split('%').next()?;
+
+    address.parse::<IpAddr>().ok()
+}
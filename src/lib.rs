@@ -2,5 +2,16 @@ mod msg;
 mod serialize;
 mod resource_record;
 mod domain_name;
+mod resolv_conf;
+mod hosts;
+mod edns;
+mod cache;
 
 pub mod requester;
+pub mod resolver;
+
+pub use domain_name::{DomainName, DomainNameError};
+pub use edns::{Edns, EdnsOption};
+pub use msg::{Message, MessageBuilder, MessageError, MessageHeader, MessageType, Opcode, Question, ResponseCode};
+pub use resource_record::{Class, HostInformation, MailExchange, MailInformation, ResourceRecord, ResourceRecordHeader, ResponseData, Service, StartOfAuthority, Text, Type};
+pub use serialize::DeserializationError;
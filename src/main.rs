@@ -1,5 +1,11 @@
-use bark_dns_resolver::requester::Requester;
+use bark_dns_resolver::resolver::Resolver;
 
 fn main() {
-    Requester::get_ipv4_address("gmail.google.com");
-}
\ No newline at end of file
+    let resolver = Resolver::default();
+
+    if let Ok(addresses) = resolver.get_ipv4_address("gmail.google.com") {
+        for address in addresses {
+            println!("{}", address);
+        }
+    }
+}
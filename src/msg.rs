@@ -1,72 +1,202 @@
-// TODO:
-// - Check if TryFrom instead of From is better. Just panicking in case of a wrong code
-// is probably not the best option. However, this implementation will work for now.
-// - Limit label length to 63 octets
+use std::{error, fmt};
 
-use std::ptr::read;
-use crate::domain_name::DomainName;
-use crate::resource_record::{AResourceRecord, Class, CNameResourceRecord, ResourceRecord, ResourceRecordFactory, ResourceRecordHeader, Type};
-use crate::serialize::{Deserialize, DeserializationError, read_u16, Serialize};
+use crate::domain_name::{DomainName, NameCompressor};
+use crate::edns::{DEFAULT_EDNS_PAYLOAD_SIZE, Edns};
+use crate::resource_record::{Class, ResourceRecord, ResourceRecordFactory, ResourceRecordHeader, ResponseData, Type};
+use crate::serialize::{Deserialize, DeserializationError, read_slice, read_u16, read_u8, Serialize};
 
 const MESSAGE_HEADER_LENGTH: usize = 12;
 const AA_FLAG_SHIFT: usize = 2;
 const TC_FLAG_SHIFT: usize = 1;
 const RA_FLAG_SHIFT: usize = 7;
+const Z_FLAG_SHIFT: usize = 6;
+const AD_FLAG_SHIFT: usize = 5;
+const CD_FLAG_SHIFT: usize = 4;
 const QR_FLAG_SHIFT: usize = 7;
 const OPCODE_SHIFT: usize = 3;
 
+/// Errors converting values from the wire into the types of a message, or
+/// putting together a message that can't be encoded.
+#[derive(Clone, Debug, PartialEq)]
 pub enum MessageError {
-    InvalidOpcode,
-    InvalidResponseCode,
-    InvalidMessageType
+    /// The QR bit can only be 0 (query) or 1 (response)
+    InvalidMessageType(u8),
+    /// A section holds more entries than its 16-bit count can tell
+    TooManyRecords { section: &'static str, count: usize },
+    /// The RDATA of a record is longer than its 16-bit RDLENGTH can tell
+    RdataTooLong { rr_type: Type, length: usize }
 }
 
-#[derive(Copy, Clone)]
-enum Opcode {
-    StandardQuery = 0,
-    StatusQuery = 2
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::InvalidMessageType(value) => write!(f, "invalid message type {}", value),
+            MessageError::TooManyRecords { section, count } =>
+                write!(f, "too many entries in the {} section: {}", section, count),
+            MessageError::RdataTooLong { rr_type, length } =>
+                write!(f, "RDATA of {} record too long: {} bytes", rr_type, length)
+        }
+    }
 }
 
-impl TryFrom<u8> for Opcode {
-    type Error = MessageError;
+impl error::Error for MessageError {}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+// Opcodes and response codes are open-ended like types and classes: values this
+// crate doesn't know about are kept as Unknown so that they are encoded back unchanged
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    StandardQuery,
+    // Inverse query, obsoleted by RFC 3425
+    InverseQuery,
+    StatusQuery,
+    // Zone change notification (see RFC 1996)
+    Notify,
+    // Dynamic update (see RFC 2136)
+    Update,
+    // DNS Stateful Operations (see RFC 8490)
+    DSO,
+    Unknown(u8)
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
         match value {
-            0 => Ok(Opcode::StandardQuery),
-            2 => Ok(Opcode::StatusQuery),
-            _ => Err(MessageError::InvalidOpcode)
+            0 => Opcode::StandardQuery,
+            1 => Opcode::InverseQuery,
+            2 => Opcode::StatusQuery,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            6 => Opcode::DSO,
+            _ => Opcode::Unknown(value)
         }
     }
 }
 
-#[derive(Copy, Clone)]
-enum ResponseCode {
-    NoError = 0,
-    FormatError = 1,
-    ServerError = 2,
-    NameError = 3,
-    NotImplementedError = 4,
-    RefusedError = 5
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::StandardQuery => 0,
+            Opcode::InverseQuery => 1,
+            Opcode::StatusQuery => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::DSO => 6,
+            Opcode::Unknown(value) => value
+        }
+    }
 }
 
-impl TryFrom<u8> for ResponseCode {
-    type Error = MessageError;
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Opcode::StandardQuery => write!(f, "QUERY"),
+            Opcode::InverseQuery => write!(f, "IQUERY"),
+            Opcode::StatusQuery => write!(f, "STATUS"),
+            Opcode::Notify => write!(f, "NOTIFY"),
+            Opcode::Update => write!(f, "UPDATE"),
+            Opcode::DSO => write!(f, "DSO"),
+            Opcode::Unknown(value) => write!(f, "OPCODE{}", value)
+        }
+    }
+}
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
+/// Response codes, including the extended ones that need the upper 8 bits
+/// carried by the OPT record (see RFC 6891, section 6.1.3).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResponseCode {
+    NoError,
+    FormatError,
+    ServerError,
+    NameError,
+    NotImplementedError,
+    RefusedError,
+    // The following five are used by dynamic updates (see RFC 2136)
+    // A name that should not exist does exist
+    YXDomain,
+    // An RRset that should not exist does exist
+    YXRRSet,
+    // An RRset that should exist does not exist
+    NXRRSet,
+    // The server is not authoritative for the zone, or the request is not authorized
+    NotAuth,
+    // A name is not within the zone of the update
+    NotZone,
+    // The EDNS version of the request is not supported
+    BadVersion,
+    // TSIG signature failure (see RFC 8945). It shares its code with BadVersion,
+    // which is how code 16 is always decoded since this crate doesn't do TSIG.
+    BadSignature,
+    // Bad or missing server cookie (see RFC 7873)
+    BadCookie,
+    Unknown(u16)
+}
+
+impl From<u16> for ResponseCode {
+    fn from(value: u16) -> Self {
         match value {
-            0 => Ok(ResponseCode::NoError),
-            1 => Ok(ResponseCode::FormatError),
-            2 => Ok(ResponseCode::ServerError),
-            3 => Ok(ResponseCode::NameError),
-            4 => Ok(ResponseCode::NotImplementedError),
-            5 => Ok(ResponseCode::RefusedError),
-            _ => Err(MessageError::InvalidResponseCode)
+            0 => ResponseCode::NoError,
+            1 => ResponseCode::FormatError,
+            2 => ResponseCode::ServerError,
+            3 => ResponseCode::NameError,
+            4 => ResponseCode::NotImplementedError,
+            5 => ResponseCode::RefusedError,
+            6 => ResponseCode::YXDomain,
+            7 => ResponseCode::YXRRSet,
+            8 => ResponseCode::NXRRSet,
+            9 => ResponseCode::NotAuth,
+            10 => ResponseCode::NotZone,
+            16 => ResponseCode::BadVersion,
+            23 => ResponseCode::BadCookie,
+            _ => ResponseCode::Unknown(value)
         }
     }
 }
 
-#[derive(Copy, Clone)]
-enum MessageType {
+impl From<ResponseCode> for u16 {
+    fn from(value: ResponseCode) -> Self {
+        match value {
+            ResponseCode::NoError => 0,
+            ResponseCode::FormatError => 1,
+            ResponseCode::ServerError => 2,
+            ResponseCode::NameError => 3,
+            ResponseCode::NotImplementedError => 4,
+            ResponseCode::RefusedError => 5,
+            ResponseCode::YXDomain => 6,
+            ResponseCode::YXRRSet => 7,
+            ResponseCode::NXRRSet => 8,
+            ResponseCode::NotAuth => 9,
+            ResponseCode::NotZone => 10,
+            ResponseCode::BadVersion | ResponseCode::BadSignature => 16,
+            ResponseCode::BadCookie => 23,
+            ResponseCode::Unknown(value) => value
+        }
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseCode::NoError => write!(f, "NOERROR"),
+            ResponseCode::FormatError => write!(f, "FORMERR"),
+            ResponseCode::ServerError => write!(f, "SERVFAIL"),
+            ResponseCode::NameError => write!(f, "NXDOMAIN"),
+            ResponseCode::NotImplementedError => write!(f, "NOTIMP"),
+            ResponseCode::RefusedError => write!(f, "REFUSED"),
+            ResponseCode::YXDomain => write!(f, "YXDOMAIN"),
+            ResponseCode::YXRRSet => write!(f, "YXRRSET"),
+            ResponseCode::NXRRSet => write!(f, "NXRRSET"),
+            ResponseCode::NotAuth => write!(f, "NOTAUTH"),
+            ResponseCode::NotZone => write!(f, "NOTZONE"),
+            ResponseCode::BadVersion => write!(f, "BADVERS"),
+            ResponseCode::BadSignature => write!(f, "BADSIG"),
+            ResponseCode::BadCookie => write!(f, "BADCOOKIE"),
+            ResponseCode::Unknown(value) => write!(f, "RCODE{}", value)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageType {
     Query = 0,
     Response = 1
 }
@@ -78,12 +208,13 @@ impl TryFrom<u8> for MessageType {
         match value {
             0 => Ok(MessageType::Query),
             1 => Ok(MessageType::Response),
-            _ => Err(MessageError::InvalidMessageType)
+            _ => Err(MessageError::InvalidMessageType(value))
         }
     }
 }
 
-pub(crate) struct MessageHeader {
+#[derive(Clone, Debug)]
+pub struct MessageHeader {
     id: u16,
     qr: MessageType,
     opcode: Opcode,
@@ -91,6 +222,12 @@ pub(crate) struct MessageHeader {
     truncation: bool,
     recursion_desired: bool,
     recursion_available: bool,
+    // Reserved, must be zero in messages sent but is kept as received
+    z: bool,
+    // DNSSEC flags (see RFC 4035, section 3.2)
+    authentic_data: bool,
+    checking_disabled: bool,
+    // Only the 4 bits held by the header
     response_code: ResponseCode,
     qdcount: u16,
     ancount: u16,
@@ -108,6 +245,9 @@ impl MessageHeader {
             truncation: false,
             recursion_desired: true,
             recursion_available: false,
+            z: false,
+            authentic_data: false,
+            checking_disabled: false,
             response_code: ResponseCode::NoError,
             qdcount: 1,
             ancount: 0,
@@ -115,6 +255,80 @@ impl MessageHeader {
             arcount: 0
         }
     }
+
+    /// Whether this is the header of a response to a message with header `query`
+    pub(crate) fn is_response_to(&self, query: &MessageHeader) -> bool {
+        self.qr == MessageType::Response && self.id == query.id
+    }
+
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        self.qr
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    /// Whether the responding server is an authority for the name in the question
+    pub fn authoritative(&self) -> bool {
+        self.authoritative
+    }
+
+    /// Whether the message was truncated to fit in a UDP datagram
+    pub fn truncation(&self) -> bool {
+        self.truncation
+    }
+
+    pub fn recursion_desired(&self) -> bool {
+        self.recursion_desired
+    }
+
+    pub fn recursion_available(&self) -> bool {
+        self.recursion_available
+    }
+
+    /// The reserved Z bit, which should always be unset
+    pub fn z(&self) -> bool {
+        self.z
+    }
+
+    /// Whether the server considers all the data of the response authentic,
+    /// i.e. validated with DNSSEC
+    pub fn authentic_data(&self) -> bool {
+        self.authentic_data
+    }
+
+    /// Whether the client asked the server not to validate the data with DNSSEC
+    pub fn checking_disabled(&self) -> bool {
+        self.checking_disabled
+    }
+
+    /// Response code given by the 4 bits of the header. Extended codes also need
+    /// the OPT record, see [`Message::response_code`].
+    pub fn response_code(&self) -> ResponseCode {
+        self.response_code
+    }
+
+    pub fn question_count(&self) -> u16 {
+        self.qdcount
+    }
+
+    pub fn answer_count(&self) -> u16 {
+        self.ancount
+    }
+
+    pub fn authority_count(&self) -> u16 {
+        self.nscount
+    }
+
+    /// Number of records in the additional section, including the OPT record
+    pub fn additional_count(&self) -> u16 {
+        self.arcount
+    }
 }
 
 impl Serialize for MessageHeader {
@@ -125,16 +339,19 @@ impl Serialize for MessageHeader {
         bytes.extend_from_slice(&id_bytes);
 
         let qr= self.qr as u8;
-        let opcode = self.opcode as u8;
+        let opcode = u8::from(self.opcode) & 0x0F;
         let aa: u8 = if self.authoritative { 1 } else { 0 };
         let tc: u8 = if self.truncation { 1 } else { 0 };
         let rd: u8 = if self.recursion_desired { 1 } else { 0 };
         let ra: u8 = if self.recursion_available { 1 } else { 0 };
-        let rcode = self.response_code as u8;
+        let z: u8 = if self.z { 1 } else { 0 };
+        let ad: u8 = if self.authentic_data { 1 } else { 0 };
+        let cd: u8 = if self.checking_disabled { 1 } else { 0 };
+        let rcode = (u16::from(self.response_code) & 0x0F) as u8;
 
         let upper_flags =
             qr << QR_FLAG_SHIFT | opcode << OPCODE_SHIFT | aa << AA_FLAG_SHIFT | tc << TC_FLAG_SHIFT | rd;
-        let lower_flags = ra << RA_FLAG_SHIFT | 0 << 6 | 0 << 5 | 0 << 4 | rcode;
+        let lower_flags = ra << RA_FLAG_SHIFT | z << Z_FLAG_SHIFT | ad << AD_FLAG_SHIFT | cd << CD_FLAG_SHIFT | rcode;
         bytes.extend_from_slice(&[upper_flags, lower_flags]);
 
         let counts = [self.qdcount, self.ancount, self.nscount, self.arcount];
@@ -153,37 +370,31 @@ impl Deserialize for MessageHeader {
     where
         Self: Sized
     {
-        if offset + MESSAGE_HEADER_LENGTH > bytes.len() {
-            return Err(DeserializationError::BufferOverflow);
-        }
+        read_slice(bytes, offset, MESSAGE_HEADER_LENGTH)?;
 
         let mut read_bytes = 0usize;
         let (off, id) = read_u16(bytes, offset)?;
         read_bytes += off;
 
-        let flags = bytes[offset + read_bytes];
-        read_bytes += 1;
-
-        let qr = match MessageType::try_from((flags & 0b10000000) >> QR_FLAG_SHIFT) {
-            Ok(qr) => qr,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid QR, {:?}", (flags & 0b10000000) >> QR_FLAG_SHIFT)))
-        };
-        let opcode = match Opcode::try_from((flags & 0b01111000) >> OPCODE_SHIFT) {
-            Ok(opcode) => opcode,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid Opcode, {:?}", (flags & 0b10000000) >> QR_FLAG_SHIFT)))
-        };
+        let (off, flags) = read_u8(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let qr = (flags & 0b10000000) >> QR_FLAG_SHIFT;
+        let qr = MessageType::try_from(qr)
+            .map_err(|_| DeserializationError::invalid_data(offset + read_bytes - off, "QR", qr.into()))?;
+        let opcode = Opcode::from((flags & 0b01111000) >> OPCODE_SHIFT);
         let aa = (flags & 0b00000100) >> AA_FLAG_SHIFT;
         let tc = (flags & 0b00000010) >> TC_FLAG_SHIFT;
         let rd = flags & 0b00000001;
 
-        let flags = bytes[offset + read_bytes];
-        read_bytes += 1;
+        let (off, flags) = read_u8(bytes, offset + read_bytes)?;
+        read_bytes += off;
 
         let ra = (flags & 0b10000000) >> RA_FLAG_SHIFT;
-        let response_code = match ResponseCode::try_from(flags & 0b00001111) {
-            Ok(rc) => rc,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid Response Code, {:?}", (flags & 0b10000000) >> QR_FLAG_SHIFT)))
-        };
+        let z = (flags & 0b01000000) >> Z_FLAG_SHIFT;
+        let ad = (flags & 0b00100000) >> AD_FLAG_SHIFT;
+        let cd = (flags & 0b00010000) >> CD_FLAG_SHIFT;
+        let response_code = ResponseCode::from((flags & 0b00001111) as u16);
 
         let (off, qdcount) = read_u16(bytes, offset + read_bytes)?;
         read_bytes += off;
@@ -205,6 +416,9 @@ impl Deserialize for MessageHeader {
             truncation: tc == 1,
             recursion_desired: rd == 1,
             recursion_available: ra == 1,
+            z: z == 1,
+            authentic_data: ad == 1,
+            checking_disabled: cd == 1,
             response_code,
             qdcount,
             ancount,
@@ -214,14 +428,15 @@ impl Deserialize for MessageHeader {
     }
 }
 
-pub(crate) struct Question {
+#[derive(Clone, Debug, PartialEq)]
+pub struct Question {
     qname: DomainName,
     qtype: Type,
     qclass: Class
 }
 
 impl Question {
-    fn new(qname: DomainName, qtype: Type, qclass: Class) -> Self {
+    pub fn new(qname: DomainName, qtype: Type, qclass: Class) -> Self {
         Self {
             qname,
             qtype,
@@ -229,12 +444,16 @@ impl Question {
         }
     }
 
-    fn new_from_domain_name(qname: DomainName) -> Self {
-        Self {
-            qname,
-            qtype: Type::A,
-            qclass: Class::Internet
-        }
+    pub fn qname(&self) -> &DomainName {
+        &self.qname
+    }
+
+    pub fn qtype(&self) -> Type {
+        self.qtype
+    }
+
+    pub fn qclass(&self) -> Class {
+        self.qclass
     }
 }
 
@@ -245,16 +464,24 @@ impl Serialize for Question {
         let hostname_bytes = self.qname.serialize();
         bytes.extend_from_slice(&hostname_bytes);
 
-        let qtype_bytes = self.qtype as u16;
+        let qtype_bytes = u16::from(self.qtype);
         bytes.extend_from_slice(&qtype_bytes.to_be_bytes());
 
-        let qclass_bytes = self.qclass as u16;
+        let qclass_bytes = u16::from(self.qclass);
         bytes.extend_from_slice(&qclass_bytes.to_be_bytes());
 
         bytes
     }
 }
 
+impl Question {
+    fn serialize_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.qname, bytes);
+        bytes.extend_from_slice(&u16::from(self.qtype).to_be_bytes());
+        bytes.extend_from_slice(&u16::from(self.qclass).to_be_bytes());
+    }
+}
+
 impl Deserialize for Question {
     fn deserialize(bytes: &[u8], offset: usize)
         -> Result<(usize, Self), DeserializationError>
@@ -262,21 +489,15 @@ impl Deserialize for Question {
         Self: Sized
     {
         let mut read_bytes = 0usize;
-        let (off, qname) = DomainName::deserialize(&bytes, offset)?;
+        let (off, qname) = DomainName::deserialize(bytes, offset)?;
         read_bytes += off;
 
         let (off, qtype) = read_u16(bytes, offset + read_bytes)?;
-        let qtype = match Type::try_from(qtype) {
-            Ok(qtype) => qtype,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid QType, {:?}", qtype)))
-        };
+        let qtype = Type::from(qtype);
         read_bytes += off;
 
         let (off, qclass) = read_u16(bytes, offset + read_bytes)?;
-        let qclass = match Class::try_from(qclass) {
-            Ok(qtype) => qtype,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid QClass, {:?}", qclass)))
-        };
+        let qclass = Class::from(qclass);
         read_bytes += off;
 
         Ok((read_bytes, Self {
@@ -287,99 +508,733 @@ impl Deserialize for Question {
     }
 }
 
-pub(crate) struct DNSMessage {
+// Records of a section of a message
+type Records = Vec<Box<dyn ResourceRecord>>;
+
+/// A DNS message, either a query or a response (see RFC 1035, section 4).
+/// Messages are put together with a [`MessageBuilder`].
+#[derive(Debug)]
+pub struct Message {
     header: MessageHeader,
-    question: Question,
-    answers: Option<Vec<Box<dyn ResourceRecord>>>,
-    authorities: Option<Vec<Box<dyn ResourceRecord>>>,
-    additional: Option<Vec<Box<dyn ResourceRecord>>>
-}
-
-impl DNSMessage {
-    pub(crate) fn new_from_components(
-        header: MessageHeader,
-        question: Question,
-        answers: Option<Vec<Box<dyn ResourceRecord>>>,
-        authorities: Option<Vec<Box<dyn ResourceRecord>>>,
-        additional: Option<Vec<Box<dyn ResourceRecord>>>) -> Self {
-        Self {
-            header,
-            question,
-            answers,
-            authorities,
-            additional
+    questions: Vec<Question>,
+    answers: Option<Records>,
+    authorities: Option<Records>,
+    additional: Option<Records>,
+    // OPT pseudo-record of the additional section, if any
+    edns: Option<Edns>
+}
+
+impl Message {
+    /// Decodes a message from its wire format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DeserializationError> {
+        let (_, msg) = Self::deserialize(bytes, 0)?;
+
+        Ok(msg)
+    }
+
+    /// Encodes the message in wire format, with its names compressed
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.serialize()
+    }
+
+    /// A standard query for records of type `qtype` and class `qclass` of `name`,
+    /// with a random ID and asking for recursion. `qtype` may also be one of the
+    /// types only valid in questions, such as [`Type::ANY`] or [`Type::AXFR`].
+    pub fn query(name: DomainName, qtype: Type, qclass: Class) -> Self {
+        MessageBuilder::query(Question::new(name, qtype, qclass)).build()
+            .expect("a single question always fits in a message")
+    }
+
+    /// Attaches an OPT pseudo-record to the additional section, replacing any previous one
+    pub(crate) fn with_edns(mut self, edns: Edns) -> Result<Self, MessageError> {
+        check_edns(&edns)?;
+
+        if self.edns.is_none() {
+            self.header.arcount = section_count("additional", self.additional().len() + 1)?;
         }
+        self.edns = Some(edns);
+        Ok(self)
     }
 
-    pub(crate) fn new_query_from_hostname(hostname: DomainName) -> Self {
-        let id = rand::random::<u16>();
-        let header = MessageHeader::standard_query_from_id(id);
-        let question = Question::new_from_domain_name(hostname);
+    pub fn edns(&self) -> Option<&Edns> {
+        self.edns.as_ref()
+    }
 
-        Self {
-            header,
-            question,
-            answers: None,
-            authorities: None,
-            additional: None
+    /// Response code of the message, including the upper 8 bits carried by the
+    /// OPT record when EDNS is in use.
+    pub fn response_code(&self) -> ResponseCode {
+        let header_rcode = u16::from(self.header.response_code);
+
+        match &self.edns {
+            Some(edns) => ResponseCode::from(edns.full_response_code(header_rcode as u8)),
+            None => self.header.response_code
+        }
+    }
+
+    pub fn header(&self) -> &MessageHeader {
+        &self.header
+    }
+
+    pub fn questions(&self) -> &[Question] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> &[Box<dyn ResourceRecord>] {
+        self.answers.as_deref().unwrap_or_default()
+    }
+
+    pub fn authorities(&self) -> &[Box<dyn ResourceRecord>] {
+        self.authorities.as_deref().unwrap_or_default()
+    }
+
+    /// Records of the additional section, without the OPT record (see [`Message::edns`])
+    pub fn additional(&self) -> &[Box<dyn ResourceRecord>] {
+        self.additional.as_deref().unwrap_or_default()
+    }
+
+    /// Follows the CNAME records of the answer section starting at `name` and
+    /// returns the canonical name the answers are ultimately given for.
+    pub(crate) fn canonical_name(&self, name: &DomainName) -> DomainName {
+        let mut current = name.clone();
+
+        // Every hop consumes a different answer, so a longer chain must contain a loop
+        for _ in 0..self.answers().len() {
+            let next = self.answers().iter()
+                .filter(|rr| rr.header().name() == &current)
+                .find_map(|rr| match rr.data() {
+                    ResponseData::CName(cname) => Some(cname),
+                    _ => None
+                });
+
+            match next {
+                Some(cname) => current = cname,
+                None => break
+            }
         }
+
+        current
     }
 }
 
-impl Serialize for DNSMessage {
+impl Serialize for Message {
+    // Names are compressed whenever possible, which keeps answers with many
+    // records of the same zone well below the UDP size limit
     fn serialize(&self) -> Vec<u8> {
-        let unflattened_bytes = vec![
-            self.header.serialize(),
-            self.question.serialize()
-        ];
+        let mut bytes = self.header.serialize();
+        let mut compressor = NameCompressor::new();
 
-        unflattened_bytes.concat()
+        for question in self.questions.iter() {
+            question.serialize_compressed(&mut bytes, &mut compressor);
+        }
+
+        for section in [&self.answers, &self.authorities, &self.additional] {
+            for rr in section.iter().flatten() {
+                rr.serialize_compressed(&mut bytes, &mut compressor);
+            }
+        }
+
+        // The OPT record goes last, after the rest of the additional section
+        if let Some(edns) = &self.edns {
+            bytes.extend_from_slice(&edns.serialize());
+        }
+
+        bytes
     }
 }
 
-impl Deserialize for DNSMessage {
-    fn deserialize(bytes: &[u8], offset: usize) -> Result<(usize, Self), DeserializationError>{
-        let mut read_bytes = 0usize;
-        let (off, header) = MessageHeader::deserialize(bytes, offset)?;
-        read_bytes += off;
+impl Message {
+    /// Reads `count` resource records starting at `offset`. Used for the answer,
+    /// authority and additional sections, which all share the same layout. An
+    /// OPT pseudo-record is not added to the records but stored in `edns`, which
+    /// is None for the sections it is not allowed in.
+    fn deserialize_section(bytes: &[u8], offset: usize, count: u16, mut edns: Option<&mut Option<Edns>>)
+        -> Result<(usize, Option<Records>), DeserializationError> {
+        if count == 0 {
+            return Ok((0, None));
+        }
 
-        let (off, question) = Question::deserialize(bytes, offset + read_bytes)?;
-        read_bytes += off;
+        let mut read_bytes = 0usize;
+        let mut records: Records = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if Edns::is_opt_record(bytes, offset + read_bytes)? {
+                // There can only be one OPT record, in the additional section
+                // (see RFC 6891, section 6.1.1)
+                let edns = match edns.as_deref_mut() {
+                    Some(edns @ None) => edns,
+                    _ => return Err(DeserializationError::invalid_data(
+                        offset + read_bytes, "record type", u16::from(Type::OPT).into()))
+                };
 
-        let mut answers = None;
-        if header.ancount != 0 {
-            let mut answers_vec: Vec<Box<dyn ResourceRecord>> = Vec::new();
-            for _ in 0..header.ancount {
-                let (off, rr_header) =
-                    ResourceRecordHeader::deserialize(bytes, offset + read_bytes)?;
+                let (off, opt) = Edns::deserialize(bytes, offset + read_bytes)?;
                 read_bytes += off;
 
-                let (off, rr) =
-                    ResourceRecordFactory::get_rr(rr_header, bytes, offset + read_bytes)?;
-                read_bytes += off;
+                *edns = Some(opt);
+                continue;
+            }
+
+            let (off, rr_header) =
+                ResourceRecordHeader::deserialize(bytes, offset + read_bytes)?;
+            read_bytes += off;
 
-                answers_vec.push(rr);
+            // Decoders may read names from anywhere in the message through pointers,
+            // but the RDATA itself must span exactly RDLENGTH bytes
+            let rdlength = rr_header.rdlength() as usize;
+            read_slice(bytes, offset + read_bytes, rdlength)?;
+
+            let (off, rr) =
+                ResourceRecordFactory::get_rr(rr_header, bytes, offset + read_bytes)?;
+            if off != rdlength {
+                // RDLENGTH is the last field of the header, right before the RDATA
+                return Err(DeserializationError::invalid_data(
+                    offset + read_bytes - 2, "RDLENGTH", rdlength as u64))
             }
+            read_bytes += off;
 
-            answers = Some(answers_vec);
+            records.push(rr);
         }
 
-        let authorities = None;
-        if header.nscount != 0 {
+        Ok((read_bytes, Some(records)))
+    }
+}
 
-        }
+impl Deserialize for Message {
+    fn deserialize(bytes: &[u8], offset: usize) -> Result<(usize, Self), DeserializationError>{
+        let mut read_bytes = 0usize;
+        let (off, header) = MessageHeader::deserialize(bytes, offset)?;
+        read_bytes += off;
 
-        let additional = None;
-        if header.arcount != 0 {
+        let mut questions = Vec::with_capacity(header.qdcount as usize);
+        for _ in 0..header.qdcount {
+            let (off, question) = Question::deserialize(bytes, offset + read_bytes)?;
+            read_bytes += off;
 
+            questions.push(question);
         }
 
+        let mut edns = None;
+
+        let (off, answers) =
+            Self::deserialize_section(bytes, offset + read_bytes, header.ancount, None)?;
+        read_bytes += off;
+
+        let (off, authorities) =
+            Self::deserialize_section(bytes, offset + read_bytes, header.nscount, None)?;
+        read_bytes += off;
+
+        let (off, additional) =
+            Self::deserialize_section(bytes, offset + read_bytes, header.arcount, Some(&mut edns))?;
+        read_bytes += off;
+
         Ok((read_bytes, Self {
             header,
-            question,
+            questions,
             answers,
             authorities,
-            additional
+            additional,
+            edns
         }))
     }
 }
+
+/// Puts together a [`Message`]. The counts of the header are worked out from the
+/// questions and records added to it.
+#[derive(Debug)]
+pub struct MessageBuilder {
+    header: MessageHeader,
+    questions: Vec<Question>,
+    answers: Vec<Box<dyn ResourceRecord>>,
+    authorities: Vec<Box<dyn ResourceRecord>>,
+    additional: Vec<Box<dyn ResourceRecord>>,
+    edns: Option<Edns>
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBuilder {
+    /// Starts a standard query without any question, with a random ID and asking
+    /// for recursion
+    pub fn new() -> Self {
+        Self {
+            header: MessageHeader::standard_query_from_id(rand::random::<u16>()),
+            questions: vec![],
+            answers: vec![],
+            authorities: vec![],
+            additional: vec![],
+            edns: None
+        }
+    }
+
+    /// Starts a standard query for `question`, with a random ID and asking for recursion
+    pub fn query(question: Question) -> Self {
+        Self::new().with_question(question)
+    }
+
+    /// Starts a response to `query`, with its ID, opcode, questions and RD flag
+    pub fn response_to(query: &Message) -> Self {
+        let mut builder = Self::new()
+            .with_id(query.header.id)
+            .with_message_type(MessageType::Response)
+            .with_opcode(query.header.opcode);
+        builder.header.recursion_desired = query.header.recursion_desired;
+        builder.questions = query.questions.clone();
+
+        builder
+    }
+
+    pub fn with_id(mut self, id: u16) -> Self {
+        self.header.id = id;
+        self
+    }
+
+    pub fn with_message_type(mut self, message_type: MessageType) -> Self {
+        self.header.qr = message_type;
+        self
+    }
+
+    pub fn with_opcode(mut self, opcode: Opcode) -> Self {
+        self.header.opcode = opcode;
+        self
+    }
+
+    pub fn with_authoritative(mut self, authoritative: bool) -> Self {
+        self.header.authoritative = authoritative;
+        self
+    }
+
+    pub fn with_truncation(mut self, truncation: bool) -> Self {
+        self.header.truncation = truncation;
+        self
+    }
+
+    pub fn with_recursion_desired(mut self, recursion_desired: bool) -> Self {
+        self.header.recursion_desired = recursion_desired;
+        self
+    }
+
+    pub fn with_recursion_available(mut self, recursion_available: bool) -> Self {
+        self.header.recursion_available = recursion_available;
+        self
+    }
+
+    /// Sets the response code. The upper bits of extended codes go in the OPT
+    /// record, which is added if there is none yet.
+    pub fn with_response_code(mut self, response_code: ResponseCode) -> Self {
+        self.header.response_code = response_code;
+        self
+    }
+
+    pub fn with_authentic_data(mut self, authentic_data: bool) -> Self {
+        self.header.authentic_data = authentic_data;
+        self
+    }
+
+    pub fn with_checking_disabled(mut self, checking_disabled: bool) -> Self {
+        self.header.checking_disabled = checking_disabled;
+        self
+    }
+
+    /// Adds a question to the question section. Most servers only answer
+    /// messages with exactly one.
+    pub fn with_question(mut self, question: Question) -> Self {
+        self.questions.push(question);
+        self
+    }
+
+    /// Adds a record holding `data` to the answer section
+    pub fn with_answer(mut self, name: DomainName, rr_class: Class, ttl: u32, data: ResponseData) -> Self {
+        self.answers.push(ResourceRecordFactory::from_data(name, rr_class, ttl, data));
+        self
+    }
+
+    /// Adds a record holding `data` to the authority section
+    pub fn with_authority(mut self, name: DomainName, rr_class: Class, ttl: u32, data: ResponseData) -> Self {
+        self.authorities.push(ResourceRecordFactory::from_data(name, rr_class, ttl, data));
+        self
+    }
+
+    /// Adds a record holding `data` to the additional section
+    pub fn with_additional(mut self, name: DomainName, rr_class: Class, ttl: u32, data: ResponseData) -> Self {
+        self.additional.push(ResourceRecordFactory::from_data(name, rr_class, ttl, data));
+        self
+    }
+
+    /// Adds an OPT record with `edns` to the additional section, replacing any previous one
+    pub fn with_edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+
+    /// Puts the message together. Fails if a section holds more than 65535
+    /// entries or a record more than 65535 bytes of RDATA, as those don't fit
+    /// in their fields of the wire format.
+    pub fn build(self) -> Result<Message, MessageError> {
+        let mut header = self.header;
+        let mut edns = self.edns;
+
+        let response_code = u16::from(header.response_code);
+        if response_code > 0x0F {
+            edns.get_or_insert_with(|| Edns::new(DEFAULT_EDNS_PAYLOAD_SIZE))
+                .set_extended_response_code(response_code);
+            header.response_code = ResponseCode::from(response_code & 0x0F);
+        }
+
+        for rr in self.answers.iter().chain(self.authorities.iter()).chain(self.additional.iter()) {
+            let length = rr.serialize_rdata().len();
+            if length > u16::MAX as usize {
+                return Err(MessageError::RdataTooLong { rr_type: rr.header().rr_type(), length });
+            }
+        }
+        if let Some(edns) = &edns {
+            check_edns(edns)?;
+        }
+
+        header.qdcount = section_count("question", self.questions.len())?;
+        header.ancount = section_count("answer", self.answers.len())?;
+        header.nscount = section_count("authority", self.authorities.len())?;
+        header.arcount = section_count("additional", self.additional.len() + edns.is_some() as usize)?;
+
+        // Empty sections are left as None, the same as when a message is decoded
+        let section = |records: Vec<Box<dyn ResourceRecord>>| Some(records).filter(|records| !records.is_empty());
+
+        Ok(Message {
+            header,
+            questions: self.questions,
+            answers: section(self.answers),
+            authorities: section(self.authorities),
+            additional: section(self.additional),
+            edns
+        })
+    }
+}
+
+fn section_count(section: &'static str, count: usize) -> Result<u16, MessageError> {
+    u16::try_from(count).map_err(|_| MessageError::TooManyRecords { section, count })
+}
+
+fn check_edns(edns: &Edns) -> Result<(), MessageError> {
+    let length = edns.rdata_len();
+    if length > u16::MAX as usize {
+        return Err(MessageError::RdataTooLong { rr_type: Type::OPT, length });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+    use crate::edns::EdnsOption;
+    use crate::resource_record::{MailExchange, Service, StartOfAuthority, Text};
+
+    // Referral from a .com server for www.example.com: no answers, the name
+    // servers of example.com in the authority section and their addresses as glue
+    const REFERRAL: [u8; 157] = [
+        // ID 0x8a3f, response, RD, 1 question, 2 authority and 4 additional records
+        0x8a, 0x3f, 0x81, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x04,
+        // www.example.com. A IN
+        0x03, b'w', b'w', b'w', 0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+        // example.com. 172800 IN NS ns1.example.com.
+        0xc0, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x06,
+        0x03, b'n', b's', b'1', 0xc0, 0x10,
+        // example.com. 172800 IN NS ns2.example.com.
+        0xc0, 0x10, 0x00, 0x02, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x06,
+        0x03, b'n', b's', b'2', 0xc0, 0x10,
+        // ns1.example.com. 172800 IN A 192.0.2.53
+        0xc0, 0x2d, 0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x04,
+        192, 0, 2, 53,
+        // ns1.example.com. 172800 IN AAAA 2001:db8::53
+        0xc0, 0x2d, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x10,
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x53,
+        // ns2.example.com. 172800 IN A 198.51.100.53
+        0xc0, 0x3f, 0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x04,
+        198, 51, 100, 53,
+        // ns2.example.com. 172800 IN AAAA 2001:db8::1:53
+        0xc0, 0x3f, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x02, 0xa3, 0x00, 0x00, 0x10,
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00, 0x53
+    ];
+
+    // Authoritative answer for example.com. A, with EDNS: the OPT record follows
+    // the glue in the additional section
+    const ANSWER_WITH_OPT: [u8; 101] = [
+        // ID 0x1c2d, response, AA, RD, RA, 1 question, 1 answer, 1 authority and
+        // 2 additional records
+        0x1c, 0x2d, 0x85, 0x80, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x02,
+        // example.com. A IN
+        0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00,
+        0x00, 0x01, 0x00, 0x01,
+        // example.com. 3600 IN A 192.0.2.10
+        0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x04,
+        192, 0, 2, 10,
+        // example.com. 86400 IN NS ns.example.com.
+        0xc0, 0x0c, 0x00, 0x02, 0x00, 0x01, 0x00, 0x01, 0x51, 0x80, 0x00, 0x05,
+        0x02, b'n', b's', 0xc0, 0x0c,
+        // ns.example.com. 86400 IN A 192.0.2.53
+        0xc0, 0x39, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x51, 0x80, 0x00, 0x04,
+        192, 0, 2, 53,
+        // OPT: 1232 byte payload, version 0, DO set, with an 8 byte client cookie
+        0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x80, 0x00, 0x00, 0x0c,
+        0x00, 0x0a, 0x00, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08
+    ];
+
+    // Root owner name, type OPT, 1232 byte payload, no flags and no options
+    const OPT_RECORD: [u8; 11] = [0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0];
+
+    fn header(qdcount: u16, ancount: u16, nscount: u16, arcount: u16) -> Vec<u8> {
+        let mut bytes = vec![0x12, 0x34, 0x81, 0x80];
+        for count in [qdcount, ancount, nscount, arcount] {
+            bytes.extend_from_slice(&count.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn deserialization_error(bytes: &[u8]) -> DeserializationError {
+        match Message::from_bytes(bytes) {
+            Ok(_) => panic!("{:?} should not decode", bytes),
+            Err(e) => e
+        }
+    }
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    fn records(section: &[Box<dyn ResourceRecord>]) -> Vec<(String, Class, u32, ResponseData)> {
+        section.iter()
+            .map(|rr| (rr.header().name().to_string(), rr.header().rr_class(), rr.header().ttl(), rr.data()))
+            .collect()
+    }
+
+    // Encodes `msg` and decodes it again, checking that nothing was lost
+    fn assert_round_trip(msg: &Message) {
+        let decoded = Message::from_bytes(&msg.to_bytes()).unwrap();
+
+        assert_eq!(decoded.header().id(), msg.header().id());
+        assert_eq!(decoded.header().answer_count(), msg.header().answer_count());
+        assert_eq!(decoded.header().authority_count(), msg.header().authority_count());
+        assert_eq!(decoded.header().additional_count(), msg.header().additional_count());
+        assert_eq!(decoded.questions(), msg.questions());
+        assert_eq!(records(decoded.answers()), records(msg.answers()));
+        assert_eq!(records(decoded.authorities()), records(msg.authorities()));
+        assert_eq!(records(decoded.additional()), records(msg.additional()));
+        assert_eq!(decoded.edns().map(|edns| edns.options().to_vec()), msg.edns().map(|edns| edns.options().to_vec()));
+    }
+
+    #[test]
+    fn decodes_referral_with_glue() {
+        let msg = Message::from_bytes(&REFERRAL).unwrap();
+
+        assert_eq!(msg.header().id(), 0x8a3f);
+        assert_eq!(msg.header().message_type(), MessageType::Response);
+        assert!(!msg.header().authoritative());
+        assert_eq!(msg.questions(), [Question::new(name("www.example.com."), Type::A, Class::Internet)]);
+        assert!(msg.answers().is_empty());
+        assert_eq!(records(msg.authorities()), [
+            ("example.com.".to_string(), Class::Internet, 172800, ResponseData::NameServer(name("ns1.example.com."))),
+            ("example.com.".to_string(), Class::Internet, 172800, ResponseData::NameServer(name("ns2.example.com.")))
+        ]);
+        assert_eq!(records(msg.additional()), [
+            ("ns1.example.com.".to_string(), Class::Internet, 172800, ResponseData::A(Ipv4Addr::new(192, 0, 2, 53))),
+            ("ns1.example.com.".to_string(), Class::Internet, 172800,
+             ResponseData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x53))),
+            ("ns2.example.com.".to_string(), Class::Internet, 172800, ResponseData::A(Ipv4Addr::new(198, 51, 100, 53))),
+            ("ns2.example.com.".to_string(), Class::Internet, 172800,
+             ResponseData::AAAA(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 1, 0x53)))
+        ]);
+        assert!(msg.edns().is_none());
+
+        assert_round_trip(&msg);
+        assert_eq!(msg.to_bytes(), REFERRAL);
+    }
+
+    #[test]
+    fn decodes_answer_with_opt_record() {
+        let msg = Message::from_bytes(&ANSWER_WITH_OPT).unwrap();
+
+        assert_eq!(msg.header().id(), 0x1c2d);
+        assert!(msg.header().authoritative());
+        assert!(msg.header().recursion_available());
+        assert_eq!(msg.response_code(), ResponseCode::NoError);
+        assert_eq!(records(msg.answers()), [
+            ("example.com.".to_string(), Class::Internet, 3600, ResponseData::A(Ipv4Addr::new(192, 0, 2, 10)))
+        ]);
+        assert_eq!(records(msg.authorities()), [
+            ("example.com.".to_string(), Class::Internet, 86400, ResponseData::NameServer(name("ns.example.com.")))
+        ]);
+        // The OPT record is not one of the additional records, but is still counted
+        assert_eq!(records(msg.additional()), [
+            ("ns.example.com.".to_string(), Class::Internet, 86400, ResponseData::A(Ipv4Addr::new(192, 0, 2, 53)))
+        ]);
+        assert_eq!(msg.header().additional_count(), 2);
+
+        let edns = msg.edns().unwrap();
+        assert_eq!(edns.udp_payload_size(), 1232);
+        assert_eq!(edns.version(), 0);
+        assert!(edns.dnssec_ok());
+        assert_eq!(edns.options(), [EdnsOption::new(10, vec![1, 2, 3, 4, 5, 6, 7, 8])]);
+
+        assert_round_trip(&msg);
+        assert_eq!(msg.to_bytes(), ANSWER_WITH_OPT);
+    }
+
+    #[test]
+    fn keeps_edns_version_and_unknown_flags() {
+        let mut bytes = header(0, 0, 0, 1);
+        // Version 1, with DO and the lowest of the unassigned flags set
+        bytes.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 1, 0x80, 0x01, 0, 0]);
+
+        let msg = Message::from_bytes(&bytes).unwrap();
+        let edns = msg.edns().unwrap();
+        assert_eq!(edns.version(), 1);
+        assert!(edns.dnssec_ok());
+        assert_eq!(edns.z(), 1);
+        assert_eq!(msg.to_bytes(), bytes);
+
+        let mut edns = Edns::new(1232);
+        edns.set_version(2);
+        let msg = MessageBuilder::new().with_edns(edns).build().unwrap();
+        assert_eq!(Message::from_bytes(&msg.to_bytes()).unwrap().edns().unwrap().version(), 2);
+    }
+
+    #[test]
+    fn refuses_sections_and_rdata_too_long_for_their_fields() {
+        let query = Message::query(name("example.com."), Type::TXT, Class::Internet);
+
+        let result = MessageBuilder::response_to(&query)
+            .with_answer(name("example.com."), Class::Internet, 3600,
+                         ResponseData::Unknown { rtype: 65280, rdata: vec![0; 65536] })
+            .build();
+        assert_eq!(result.unwrap_err(), MessageError::RdataTooLong { rr_type: Type::Unknown(65280), length: 65536 });
+
+        // 258 strings of 254 bytes, each with its length byte
+        let result = MessageBuilder::response_to(&query)
+            .with_answer(name("example.com."), Class::Internet, 3600,
+                         ResponseData::TXT(Text::new(vec![vec![b'a'; 254]; 258])))
+            .build();
+        assert_eq!(result.unwrap_err(), MessageError::RdataTooLong { rr_type: Type::TXT, length: 65790 });
+
+        let mut edns = Edns::new(1232);
+        edns.add_option(EdnsOption::new(65001, vec![0; 65532]));
+        let result = MessageBuilder::response_to(&query).with_edns(edns).build();
+        assert_eq!(result.unwrap_err(), MessageError::RdataTooLong { rr_type: Type::OPT, length: 65536 });
+
+        let mut builder = MessageBuilder::response_to(&query);
+        for _ in 0..65535 {
+            builder = builder.with_additional(name("example.com."), Class::Internet, 3600,
+                                              ResponseData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        }
+        let result = builder.with_edns(Edns::new(1232)).build();
+        assert_eq!(result.unwrap_err(), MessageError::TooManyRecords { section: "additional", count: 65536 });
+    }
+
+    #[test]
+    fn compression_shrinks_records_of_the_same_zone() {
+        let query = Message::query(name("example.com."), Type::ANY, Class::Internet);
+        let mut builder = MessageBuilder::response_to(&query)
+            .with_answer(name("example.com."), Class::Internet, 3600, ResponseData::SOA(StartOfAuthority::new(
+                name("ns1.example.com."), name("hostmaster.example.com."), 2024010101, 7200, 3600, 1209600, 300)));
+        for i in 1..=4 {
+            builder = builder
+                .with_answer(name("example.com."), Class::Internet, 3600,
+                             ResponseData::NameServer(name(&format!("ns{}.example.com.", i))))
+                .with_answer(name("example.com."), Class::Internet, 3600,
+                             ResponseData::MailExchange(MailExchange::new(i * 10, name(&format!("mx{}.example.com.", i)))))
+                .with_additional(name(&format!("ns{}.example.com.", i)), Class::Internet, 3600,
+                                 ResponseData::A(Ipv4Addr::new(192, 0, 2, i as u8)));
+        }
+        let msg = builder.build().unwrap();
+
+        let uncompressed_size = MESSAGE_HEADER_LENGTH
+            + msg.questions().iter().map(|question| question.serialize().len()).sum::<usize>()
+            + [msg.answers(), msg.authorities(), msg.additional()].iter()
+                .flat_map(|section| section.iter())
+                .map(|rr| rr.serialize().len())
+                .sum::<usize>();
+        let compressed_size = msg.to_bytes().len();
+
+        assert!(compressed_size < uncompressed_size,
+                "compressed to {} bytes, {} without compression", compressed_size, uncompressed_size);
+        // Every name but the first one is, or ends with, a pointer
+        assert!(compressed_size * 3 < uncompressed_size * 2,
+                "compressed to {} bytes, {} without compression", compressed_size, uncompressed_size);
+        assert_round_trip(&msg);
+    }
+
+    #[test]
+    fn names_in_srv_and_unknown_rdata_are_not_compressed() {
+        let query = Message::query(name("_sip._tcp.example.com."), Type::SRV, Class::Internet);
+        let unknown_rdata = vec![0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00];
+        let msg = MessageBuilder::response_to(&query)
+            .with_answer(name("_sip._tcp.example.com."), Class::Internet, 3600,
+                         ResponseData::SRV(Service::new(10, 60, 5060, name("sip.example.com."))))
+            .with_answer(name("_sip._tcp.example.com."), Class::Internet, 3600,
+                         ResponseData::Unknown { rtype: 65280, rdata: unknown_rdata.clone() })
+            .build().unwrap();
+        let bytes = msg.to_bytes();
+
+        // Looks for RDLENGTH followed by the RDATA
+        let contains = |rdata: &[u8]| bytes.windows(rdata.len()).any(|window| window == rdata);
+        // Priority, weight and port, then the target written in full even though
+        // "example.com" could be pointed to
+        let mut srv_rdata = vec![0x00, 0x0a, 0x00, 0x3c, 0x13, 0xc4];
+        srv_rdata.extend_from_slice(&[0x03, b's', b'i', b'p']);
+        srv_rdata.extend_from_slice(&unknown_rdata);
+        assert!(contains(&[&[0x00, 0x17], srv_rdata.as_slice()].concat()), "{:?}", bytes);
+        assert!(contains(&[&[0x00, 0x0d], unknown_rdata.as_slice()].concat()), "{:?}", bytes);
+        assert_round_trip(&msg);
+    }
+
+    #[test]
+    fn follows_pointer_chain_below_start_of_name() {
+        // The owner of the second answer points to the one of the first, which in
+        // turn ends with a pointer to the question: the second hop is read from
+        // an offset lower than the one the name starts at
+        let mut bytes = header(1, 2, 0, 0);
+        // example.com. A IN, at offset 12
+        bytes.extend_from_slice(&[0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00]);
+        bytes.extend_from_slice(&[0x00, 0x01, 0x00, 0x01]);
+        // www.example.com. 300 IN A 192.0.2.1, at offset 29
+        bytes.extend_from_slice(&[0x03, b'w', b'w', b'w', 0xc0, 0x0c]);
+        bytes.extend_from_slice(&[0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x04, 192, 0, 2, 1]);
+        // www.example.com. 300 IN A 192.0.2.2, at offset 49
+        bytes.extend_from_slice(&[0xc0, 0x1d]);
+        bytes.extend_from_slice(&[0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x04, 192, 0, 2, 2]);
+
+        let msg = Message::from_bytes(&bytes).unwrap();
+
+        assert_eq!(records(msg.answers()), [
+            ("www.example.com.".to_string(), Class::Internet, 300, ResponseData::A(Ipv4Addr::new(192, 0, 2, 1))),
+            ("www.example.com.".to_string(), Class::Internet, 300, ResponseData::A(Ipv4Addr::new(192, 0, 2, 2)))
+        ]);
+        assert_round_trip(&msg);
+    }
+
+    #[test]
+    fn rejects_second_opt_record() {
+        let mut bytes = header(0, 0, 0, 2);
+        bytes.extend_from_slice(&OPT_RECORD);
+        bytes.extend_from_slice(&OPT_RECORD);
+
+        assert!(matches!(
+            deserialization_error(&bytes),
+            DeserializationError::InvalidData { offset: 23, field: "record type", value: 41 }));
+    }
+
+    #[test]
+    fn rejects_opt_record_outside_additional_section() {
+        for counts in [(1, 0, 0), (0, 1, 0)] {
+            let mut bytes = header(0, counts.0, counts.1, counts.2);
+            bytes.extend_from_slice(&OPT_RECORD);
+
+            assert!(matches!(
+                deserialization_error(&bytes),
+                DeserializationError::InvalidData { offset: 12, field: "record type", value: 41 }));
+        }
+    }
+}
@@ -1,17 +1,87 @@
-use std::io;
-use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
-use std::str::FromStr;
+use std::{error, fmt, io};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::domain_name::DomainName;
-use crate::msg::{DNSMessage, MessageError};
+use crate::cache::Cache;
+use crate::domain_name::DomainNameError;
+use crate::msg::{Message, MessageError, MessageHeader, ResponseCode};
+use crate::resolver::{ResolverConfig, RotationPolicy};
 use crate::serialize::{Deserialize, DeserializationError, Serialize};
 
-const DEFAULT_NAME_SERVER: &str = "8.8.8.8";
-
+#[derive(Debug)]
 pub enum DNSError {
     Io(io::Error),
     Encoding(DeserializationError),
-    Message(MessageError)
+    Message(MessageError),
+    // The server answered with NXDOMAIN: the queried name does not exist
+    NonExistentDomain,
+    // The server answered with SERVFAIL: it was unable to process the query
+    ServerFailure,
+    // The server answered with FORMERR: it was unable to interpret the query
+    FormatError,
+    // The server answered with NOTIMP: it doesn't support this kind of query
+    NotImplemented,
+    // The server answered with REFUSED: it won't answer this query for policy reasons
+    Refused,
+    // The server answered with a response code that makes no sense for a query
+    UnexpectedResponseCode(ResponseCode),
+    // The resolver configuration doesn't list any name server to ask
+    NoNameServers,
+    // No name server answered within the configured timeout and retries
+    Timeout,
+    // The name to look up is not a valid domain name
+    InvalidName(DomainNameError)
+}
+
+impl DNSError {
+    /// The error reported for a response with the given code, None if the code
+    /// means success
+    pub(crate) fn from_response_code(response_code: ResponseCode) -> Option<Self> {
+        match response_code {
+            ResponseCode::NoError => None,
+            ResponseCode::NameError => Some(DNSError::NonExistentDomain),
+            ResponseCode::ServerError => Some(DNSError::ServerFailure),
+            ResponseCode::FormatError => Some(DNSError::FormatError),
+            ResponseCode::NotImplementedError => Some(DNSError::NotImplemented),
+            ResponseCode::RefusedError => Some(DNSError::Refused),
+            _ => Some(DNSError::UnexpectedResponseCode(response_code))
+        }
+    }
+}
+
+impl fmt::Display for DNSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DNSError::Io(e) => write!(f, "I/O error: {}", e),
+            DNSError::Encoding(e) => write!(f, "malformed message: {}", e),
+            DNSError::Message(e) => write!(f, "{}", e),
+            DNSError::NonExistentDomain => write!(f, "domain name does not exist"),
+            DNSError::ServerFailure => write!(f, "name server failed to process the query"),
+            DNSError::FormatError => write!(f, "name server was unable to interpret the query"),
+            DNSError::NotImplemented => write!(f, "name server doesn't support the query"),
+            DNSError::Refused => write!(f, "name server refused to answer the query"),
+            DNSError::UnexpectedResponseCode(response_code) =>
+                write!(f, "name server answered with unexpected response code {}", response_code),
+            DNSError::NoNameServers => write!(f, "no name servers configured"),
+            DNSError::Timeout => write!(f, "no name server answered in time"),
+            DNSError::InvalidName(e) => write!(f, "invalid domain name: {}", e)
+        }
+    }
+}
+
+impl error::Error for DNSError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DNSError::Io(e) => Some(e),
+            DNSError::Encoding(e) => Some(e),
+            DNSError::Message(e) => Some(e),
+            DNSError::InvalidName(e) => Some(e),
+            _ => None
+        }
+    }
 }
 
 impl From<io::Error> for DNSError {
@@ -26,35 +96,237 @@ impl From<DeserializationError> for DNSError {
     }
 }
 
+impl From<DomainNameError> for DNSError {
+    fn from(value: DomainNameError) -> Self {
+        Self::InvalidName(value)
+    }
+}
+
 impl From<MessageError> for DNSError {
     fn from(value: MessageError) -> Self {
         Self::Message(value)
     }
 }
 
-pub struct Requester;
+pub(crate) struct Requester {
+    config: ResolverConfig,
+    // Number of queries sent so far, used to rotate through the name servers
+    queries_sent: AtomicUsize,
+    spoofed_replies: AtomicUsize,
+    cache: Mutex<Cache>
+}
 
 impl Requester {
-    pub fn get_ipv4_address(name: &str) -> Result<Vec<Ipv4Addr>, DNSError> {
-        // 1. Create socket
-        let socket_addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
-        let dns_server_socket_addr = SocketAddrV4::new(Ipv4Addr::from_str(DEFAULT_NAME_SERVER).unwrap(), 53);
-        let udp_socket = UdpSocket::bind(socket_addr).expect("Could not bind to address");
+    pub(crate) fn new(config: ResolverConfig) -> Self {
+        let cache = Cache::new(config.cache_size(), config.cache_min_ttl(), config.cache_max_ttl());
+
+        Self {
+            config,
+            queries_sent: AtomicUsize::new(0),
+            spoofed_replies: AtomicUsize::new(0),
+            cache: Mutex::new(cache)
+        }
+    }
+
+    pub(crate) fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    /// Returns the configured name servers in the order they should be tried
+    /// for the next query, according to the rotation policy.
+    fn name_servers_in_order(&self) -> Vec<SocketAddr> {
+        let name_servers = self.config.name_servers();
+        if name_servers.is_empty() {
+            return vec![];
+        }
+
+        let query_number = self.queries_sent.fetch_add(1, Ordering::Relaxed);
+        let first = match self.config.rotation() {
+            RotationPolicy::InOrder => 0,
+            RotationPolicy::RoundRobin => query_number % name_servers.len(),
+            RotationPolicy::Random => rand::random::<usize>() % name_servers.len()
+        };
+
+        name_servers.iter()
+            .cycle()
+            .skip(first)
+            .take(name_servers.len())
+            .copied()
+            .collect()
+    }
+
+    /// Number of replies that were dropped because they didn't match the query
+    /// they were received for, either coming from an unexpected address,
+    /// carrying a different ID or question, or not being a valid message.
+    pub(crate) fn spoofed_replies(&self) -> usize {
+        self.spoofed_replies.load(Ordering::Relaxed)
+    }
+
+    // A panic while holding the lock can't leave the cache inconsistent, so a
+    // poisoned lock is still used
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends `query` to the configured name servers and returns the first answer
+    /// received, unless the cache still holds an answer to it. Every round tries
+    /// each server once; if none of them answers in time, the query is retried
+    /// with twice the previous timeout, up to the configured number of retries.
+    pub(crate) fn send_query(&self, query: &Message) -> Result<Message, DNSError> {
+        if let Some(msg) = self.cache().get(query, Instant::now()) {
+            return Ok(msg);
+        }
 
-        // 2. Connect socket
-        udp_socket.connect(dns_server_socket_addr).expect(format!("Couldn't connect to {:?}", dns_server_socket_addr).as_str());
+        let name_servers = self.name_servers_in_order();
+        if name_servers.is_empty() {
+            return Err(DNSError::NoNameServers);
+        }
 
-        // 3. Generate standard query, serialize it and send it through the UDP socket
-        let query = DNSMessage::new_query_from_hostname(DomainName::from_string(name));
-        let b = query.serialize();
-        let _ = udp_socket.send(b.as_slice());
+        let mut timeout = self.config.timeout();
+        let mut last_error = DNSError::Timeout;
 
-        // 5. Prepare buffer for response, deserialize message and get the result
-        let buf = &mut [0u8; 512];
-        let (_, _src) = udp_socket.recv_from(buf).expect("Didn't receive data");
+        for _ in 0..=self.config.retries() {
+            for name_server in name_servers.iter() {
+                match self.exchange(*name_server, query, timeout) {
+                    Ok(msg) => {
+                        self.cache().insert(query, &msg, Instant::now());
+                        return Ok(msg);
+                    },
+                    // Network errors are tied to one server, so move on to the next one.
+                    // Anything else would fail with every server as well.
+                    Err(e @ (DNSError::Io(_) | DNSError::Timeout)) => last_error = e,
+                    Err(e) => return Err(e)
+                }
+            }
 
-        let msg = DNSMessage::deserialize(buf, 0)?;
+            timeout = timeout.saturating_mul(2);
+        }
 
-        Ok(vec![])
+        Err(last_error)
     }
-}
\ No newline at end of file
+
+    /// Asks a single name server, over UDP unless TCP-only mode is enabled. A
+    /// truncated UDP answer is discarded and the query is repeated over TCP.
+    fn exchange(&self, name_server: SocketAddr, query: &Message, timeout: Duration)
+        -> Result<Message, DNSError> {
+        if self.config.tcp_only() {
+            return self.exchange_tcp(name_server, query, timeout);
+        }
+
+        let msg = self.exchange_udp(name_server, query, timeout)?;
+        if msg.header().truncation() {
+            return self.exchange_tcp(name_server, query, timeout);
+        }
+
+        Ok(msg)
+    }
+
+    fn exchange_udp(&self, name_server: SocketAddr, query: &Message, timeout: Duration)
+        -> Result<Message, DNSError> {
+        let deadline = Instant::now() + timeout;
+
+        // 1. Create socket of the same family as the name server. It is left
+        // unconnected so that replies from other addresses reach us and can be
+        // counted as spoofing attempts.
+        let socket_addr: SocketAddr = match name_server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let udp_socket = UdpSocket::bind(socket_addr)?;
+
+        // 2. Send the serialized query through the UDP socket
+        udp_socket.send_to(query.serialize().as_slice(), name_server)?;
+
+        // 3. Wait for a reply to this query, dropping any other until the timeout expires
+        let buf = &mut vec![0u8; self.config.udp_buffer_size()];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(DNSError::Timeout);
+            }
+            udp_socket.set_read_timeout(Some(remaining))?;
+
+            let (len, src) = udp_socket.recv_from(buf).map_err(timeout_error)?;
+            if src != name_server {
+                self.spoofed_replies.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            if let Some(msg) = self.accept_reply(query, &buf[..len]) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    // Over TCP every message is prefixed with its length as a two byte
+    // integer (see RFC 1035, section 4.2.2)
+    fn exchange_tcp(&self, name_server: SocketAddr, query: &Message, timeout: Duration)
+        -> Result<Message, DNSError> {
+        let deadline = Instant::now() + timeout;
+
+        // 1. Connect to the name server
+        let mut tcp_stream =
+            TcpStream::connect_timeout(&name_server, timeout).map_err(timeout_error)?;
+        tcp_stream.set_write_timeout(Some(timeout))?;
+
+        // 2. Send the length prefixed query
+        let query_bytes = query.serialize();
+        let query_length = u16::try_from(query_bytes.len())
+            .map_err(|_| DNSError::Io(io::Error::from(io::ErrorKind::InvalidInput)))?;
+        let mut bytes = Vec::with_capacity(query_bytes.len() + 2);
+        bytes.extend_from_slice(&query_length.to_be_bytes());
+        bytes.extend_from_slice(&query_bytes);
+        tcp_stream.write_all(&bytes).map_err(timeout_error)?;
+
+        // 3. Read messages from the stream until one replies to this query
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(DNSError::Timeout);
+            }
+            tcp_stream.set_read_timeout(Some(remaining))?;
+
+            let mut length_bytes = [0u8; 2];
+            tcp_stream.read_exact(&mut length_bytes).map_err(timeout_error)?;
+
+            let mut buf = vec![0u8; u16::from_be_bytes(length_bytes) as usize];
+            tcp_stream.read_exact(&mut buf).map_err(timeout_error)?;
+
+            if let Some(msg) = self.accept_reply(query, &buf) {
+                return Ok(msg);
+            }
+        }
+    }
+
+    /// Deserializes `bytes` if they are a reply to `query`, i.e. a response with
+    /// the same ID and question. Returns `None` (and counts the reply as spoofed)
+    /// otherwise, including when it can't be decoded, so that the caller keeps
+    /// waiting for the genuine reply.
+    fn accept_reply(&self, query: &Message, bytes: &[u8]) -> Option<Message> {
+        // Check the header first, so that only replies to this query get decoded
+        let is_reply = match MessageHeader::deserialize(bytes, 0) {
+            Ok((_, header)) => header.is_response_to(query.header()),
+            Err(_) => false
+        };
+
+        if is_reply {
+            if let Ok((_, msg)) = Message::deserialize(bytes, 0) {
+                if msg.questions() == query.questions() {
+                    return Some(msg);
+                }
+            }
+        }
+
+        self.spoofed_replies.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+}
+
+fn timeout_error(e: io::Error) -> DNSError {
+    match e.kind() {
+        // Depending on the platform, an expired timeout is reported as either
+        // WouldBlock or TimedOut
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => DNSError::Timeout,
+        _ => DNSError::Io(e)
+    }
+}
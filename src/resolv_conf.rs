@@ -0,0 +1,163 @@
+// Parser for the resolv.conf(5) format. Unknown keywords and malformed lines are
+// skipped, just like the system resolver does, so a partially valid file still
+// yields a usable configuration.
+
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::requester::DNSError;
+use crate::resolver::{ResolverConfig, RotationPolicy};
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const NAME_SERVER_PORT: u16 = 53;
+const SYSFS_NET_PATH: &str = "/sys/class/net";
+
+// Limits applied by glibc, see resolv.conf(5)
+const MAX_NAME_SERVERS: usize = 3;
+const MAX_SEARCH_DOMAINS: usize = 6;
+const MAX_NDOTS: u8 = 15;
+const MAX_TIMEOUT_SECS: u64 = 30;
+const MAX_ATTEMPTS: usize = 5;
+
+impl ResolverConfig {
+    /// Builds the configuration from the system's `/etc/resolv.conf`.
+    pub fn from_resolv_conf() -> Result<Self, DNSError> {
+        Self::from_resolv_conf_path(RESOLV_CONF_PATH)
+    }
+
+    /// Builds the configuration from a file in resolv.conf format.
+    pub fn from_resolv_conf_path<P: AsRef<Path>>(path: P) -> Result<Self, DNSError> {
+        let contents = fs::read_to_string(path)?;
+
+        Ok(Self::from_resolv_conf_str(&contents))
+    }
+
+    /// Builds the configuration from the contents of a resolv.conf file.
+    pub fn from_resolv_conf_str(contents: &str) -> Self {
+        let defaults = ResolverConfig::default();
+
+        let mut name_servers = vec![];
+        let mut search_domains = vec![];
+        let mut ndots = defaults.ndots();
+        let mut timeout = defaults.timeout();
+        let mut retries = defaults.retries();
+        let mut rotation = defaults.rotation();
+        // Unlike in the default configuration, EDNS is off unless explicitly enabled,
+        // the same as for the system resolver
+        let mut edns0 = false;
+        let mut tcp_only = defaults.tcp_only();
+
+        for line in contents.lines() {
+            // Both '#' and ';' start a comment
+            let line = match line.find(['#', ';']) {
+                Some(idx) => &line[..idx],
+                None => line
+            };
+
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue
+            };
+
+            match keyword {
+                "nameserver" => {
+                    let address = words.next().and_then(parse_name_server);
+                    if let Some(address) = address {
+                        if name_servers.len() < MAX_NAME_SERVERS {
+                            name_servers.push(address);
+                        }
+                    }
+                },
+                // "domain" and "search" are mutually exclusive: the last one wins
+                "domain" => {
+                    search_domains = words.next()
+                        .map(|domain| vec![domain.to_string()])
+                        .unwrap_or_default();
+                },
+                "search" => {
+                    search_domains = words
+                        .take(MAX_SEARCH_DOMAINS)
+                        .map(|domain| domain.to_string())
+                        .collect();
+                },
+                "options" => {
+                    for option in words {
+                        let (name, value) = match option.split_once(':') {
+                            Some((name, value)) => (name, value.parse::<u64>().ok()),
+                            None => (option, None)
+                        };
+
+                        match (name, value) {
+                            ("ndots", Some(value)) =>
+                                ndots = value.min(MAX_NDOTS as u64) as u8,
+                            ("timeout", Some(value)) =>
+                                timeout = Duration::from_secs(value.clamp(1, MAX_TIMEOUT_SECS)),
+                            // "attempts" counts the first try, retries don't
+                            ("attempts", Some(value)) =>
+                                retries = (value as usize).clamp(1, MAX_ATTEMPTS) - 1,
+                            ("rotate", _) => rotation = RotationPolicy::RoundRobin,
+                            ("edns0", _) => edns0 = true,
+                            ("use-vc", _) => tcp_only = true,
+                            _ => {}
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        // Without any name server the system resolver falls back to the local host
+        if name_servers.is_empty() {
+            name_servers.push(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), NAME_SERVER_PORT));
+        }
+
+        ResolverConfig::new(name_servers)
+            .with_search_domains(search_domains)
+            .with_ndots(ndots)
+            .with_timeout(timeout)
+            .with_retries(retries)
+            .with_rotation(rotation)
+            .with_edns0(edns0)
+            .with_tcp_only(tcp_only)
+    }
+}
+
+fn parse_name_server(address: &str) -> Option<SocketAddr> {
+    let (address, zone) = match address.split_once('%') {
+        Some((address, zone)) => (address, Some(zone)),
+        None => (address, None)
+    };
+
+    let ip = address.parse::<IpAddr>().ok()?;
+    let zone = match zone {
+        Some(zone) => zone,
+        None => return Some(SocketAddr::new(ip, NAME_SERVER_PORT))
+    };
+
+    // Link-local IPv6 addresses carry a zone ("fe80::1%eth0" or "fe80::1%2"), the
+    // interface to reach them through. Without it they can't be reached at all,
+    // so the name server is skipped if the zone can't be worked out.
+    let ipv6 = match ip {
+        IpAddr::V6(ipv6) => ipv6,
+        IpAddr::V4(_) => return None
+    };
+    let scope_id = zone.parse::<u32>().ok().or_else(|| interface_index(zone))?;
+
+    Some(SocketAddr::V6(SocketAddrV6::new(ipv6, NAME_SERVER_PORT, 0, scope_id)))
+}
+
+// Index of a network interface by name, as published by Linux in sysfs
+fn interface_index(name: &str) -> Option<u32> {
+    if name.is_empty() || name.contains('/') || name.starts_with('.') {
+        return None;
+    }
+
+    fs::read_to_string(Path::new(SYSFS_NET_PATH).join(name).join("ifindex"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,724 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::domain_name::DomainName;
+use crate::edns::{DEFAULT_EDNS_PAYLOAD_SIZE, Edns, EdnsOption};
+use crate::hosts::Hosts;
+use crate::msg::{Message, ResponseCode};
+use crate::requester::{DNSError, Requester};
+use crate::resource_record::{Class, MailExchange, ResponseData, Service, StartOfAuthority, Text, Type};
+
+const DEFAULT_NAME_SERVER: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+const DEFAULT_PORT: u16 = 53;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_RETRIES: usize = 2;
+const DEFAULT_NDOTS: u8 = 1;
+const DEFAULT_HOSTS_FILE: &str = "/etc/hosts";
+// Largest UDP message allowed without EDNS (see RFC 1035, section 2.3.4)
+const MAX_UDP_MESSAGE_SIZE: usize = 512;
+const DEFAULT_CACHE_SIZE: usize = 1024;
+const DEFAULT_CACHE_MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Decides which of the configured name servers is asked first for each query.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RotationPolicy {
+    /// Always start with the first server, using the others only as fallbacks
+    InOrder,
+    /// Start each query with the server after the one used by the previous query
+    RoundRobin,
+    /// Start each query with a randomly chosen server
+    Random
+}
+
+/// Decides which address families [`Resolver::lookup_ip`] looks up, and in which order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IpLookupPolicy {
+    Ipv4Only,
+    Ipv6Only,
+    Ipv4ThenIpv6,
+    Ipv6ThenIpv4
+}
+
+/// Settings of a [`Resolver`]: which upstream servers to ask and how.
+#[derive(Clone, Debug)]
+pub struct ResolverConfig {
+    name_servers: Vec<SocketAddr>,
+    timeout: Duration,
+    retries: usize,
+    rotation: RotationPolicy,
+    search_domains: Vec<String>,
+    ndots: u8,
+    edns0: bool,
+    edns_payload_size: u16,
+    dnssec_ok: bool,
+    edns_options: Vec<EdnsOption>,
+    tcp_only: bool,
+    ip_lookup_policy: IpLookupPolicy,
+    hosts_file: Option<PathBuf>,
+    cache_size: usize,
+    cache_min_ttl: Duration,
+    cache_max_ttl: Duration
+}
+
+impl ResolverConfig {
+    pub fn new(name_servers: Vec<SocketAddr>) -> Self {
+        Self {
+            name_servers,
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            rotation: RotationPolicy::InOrder,
+            search_domains: vec![],
+            ndots: DEFAULT_NDOTS,
+            edns0: true,
+            edns_payload_size: DEFAULT_EDNS_PAYLOAD_SIZE,
+            dnssec_ok: false,
+            edns_options: vec![],
+            tcp_only: false,
+            ip_lookup_policy: IpLookupPolicy::Ipv4ThenIpv6,
+            hosts_file: Some(PathBuf::from(DEFAULT_HOSTS_FILE)),
+            cache_size: DEFAULT_CACHE_SIZE,
+            cache_min_ttl: Duration::ZERO,
+            cache_max_ttl: DEFAULT_CACHE_MAX_TTL
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: RotationPolicy) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_search_domains(mut self, search_domains: Vec<String>) -> Self {
+        self.search_domains = search_domains;
+        self
+    }
+
+    pub fn with_ndots(mut self, ndots: u8) -> Self {
+        self.ndots = ndots;
+        self
+    }
+
+    /// Whether queries carry an OPT record (see RFC 6891)
+    pub fn with_edns0(mut self, edns0: bool) -> Self {
+        self.edns0 = edns0;
+        self
+    }
+
+    /// Size of the largest UDP answer advertised to the name servers when EDNS
+    /// is enabled. Values under 512 bytes are raised to 512.
+    pub fn with_edns_payload_size(mut self, edns_payload_size: u16) -> Self {
+        self.edns_payload_size = edns_payload_size;
+        self
+    }
+
+    /// Sets the DO bit, asking the name servers to include DNSSEC records
+    pub fn with_dnssec_ok(mut self, dnssec_ok: bool) -> Self {
+        self.dnssec_ok = dnssec_ok;
+        self
+    }
+
+    /// Options added to the OPT record of every query
+    pub fn with_edns_options(mut self, edns_options: Vec<EdnsOption>) -> Self {
+        self.edns_options = edns_options;
+        self
+    }
+
+    /// Sends every query over TCP instead of trying UDP first
+    pub fn with_tcp_only(mut self, tcp_only: bool) -> Self {
+        self.tcp_only = tcp_only;
+        self
+    }
+
+    pub fn with_ip_lookup_policy(mut self, ip_lookup_policy: IpLookupPolicy) -> Self {
+        self.ip_lookup_policy = ip_lookup_policy;
+        self
+    }
+
+    /// Answers lookups from the given hosts-format file before asking the name servers
+    pub fn with_hosts_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.hosts_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sends every lookup to the name servers, not even reading `/etc/hosts`
+    pub fn without_hosts_file(mut self) -> Self {
+        self.hosts_file = None;
+        self
+    }
+
+    /// Maximum number of answers kept in the cache, 0 disabling it. Once it is
+    /// full, the least recently used answer is dropped.
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    /// Answers are cached for at least this long, even if their TTL is shorter
+    pub fn with_cache_min_ttl(mut self, cache_min_ttl: Duration) -> Self {
+        self.cache_min_ttl = cache_min_ttl;
+        self
+    }
+
+    /// Answers are cached for at most this long, even if their TTL is longer.
+    /// It takes precedence over the minimum TTL.
+    pub fn with_cache_max_ttl(mut self, cache_max_ttl: Duration) -> Self {
+        self.cache_max_ttl = cache_max_ttl;
+        self
+    }
+
+    pub fn name_servers(&self) -> &[SocketAddr] {
+        &self.name_servers
+    }
+
+    /// Time to wait for the answer of a single query attempt
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// How many times a query is retried after the first attempt fails
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    pub fn rotation(&self) -> RotationPolicy {
+        self.rotation
+    }
+
+    /// Domains appended to names that are not fully qualified
+    pub fn search_domains(&self) -> &[String] {
+        &self.search_domains
+    }
+
+    /// Names with at least this many dots are tried as-is before the search domains
+    pub fn ndots(&self) -> u8 {
+        self.ndots
+    }
+
+    pub fn edns0(&self) -> bool {
+        self.edns0
+    }
+
+    pub fn edns_payload_size(&self) -> u16 {
+        self.edns_payload_size
+    }
+
+    pub fn dnssec_ok(&self) -> bool {
+        self.dnssec_ok
+    }
+
+    pub fn edns_options(&self) -> &[EdnsOption] {
+        &self.edns_options
+    }
+
+    /// OPT record to attach to queries, None if EDNS is disabled
+    pub(crate) fn edns(&self) -> Option<Edns> {
+        if !self.edns0 {
+            return None;
+        }
+
+        let mut edns = Edns::new(self.edns_payload_size);
+        edns.set_dnssec_ok(self.dnssec_ok);
+        for option in self.edns_options.iter() {
+            edns.add_option(option.clone());
+        }
+
+        Some(edns)
+    }
+
+    /// Size of the buffer needed to receive the largest UDP answer allowed
+    pub(crate) fn udp_buffer_size(&self) -> usize {
+        match self.edns() {
+            Some(edns) => edns.udp_payload_size() as usize,
+            None => MAX_UDP_MESSAGE_SIZE
+        }
+    }
+
+    pub fn tcp_only(&self) -> bool {
+        self.tcp_only
+    }
+
+    pub fn ip_lookup_policy(&self) -> IpLookupPolicy {
+        self.ip_lookup_policy
+    }
+
+    pub fn hosts_file(&self) -> Option<&Path> {
+        self.hosts_file.as_deref()
+    }
+
+    pub fn cache_size(&self) -> usize {
+        self.cache_size
+    }
+
+    pub fn cache_min_ttl(&self) -> Duration {
+        self.cache_min_ttl
+    }
+
+    pub fn cache_max_ttl(&self) -> Duration {
+        self.cache_max_ttl
+    }
+
+    /// Returns the names to query for `name`, in order, following the same
+    /// rules as the system resolver: a name ending with a dot is only tried
+    /// as-is, and names with fewer than `ndots` dots go through the search
+    /// domains first.
+    pub(crate) fn candidate_names(&self, name: &str) -> Vec<String> {
+        if name.ends_with('.') {
+            return vec![name.to_string()];
+        }
+
+        let searched = self.search_domains.iter()
+            .map(|domain| format!("{}.{}", name, domain.trim_end_matches('.')));
+
+        if name.matches('.').count() >= self.ndots as usize {
+            std::iter::once(name.to_string()).chain(searched).collect()
+        } else {
+            searched.chain(std::iter::once(name.to_string())).collect()
+        }
+    }
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self::new(vec![SocketAddr::new(DEFAULT_NAME_SERVER, DEFAULT_PORT)])
+    }
+}
+
+/// A host providing a service, as found by [`Resolver::lookup_srv`], along with
+/// its addresses.
+#[derive(Clone, Debug)]
+pub struct ServiceTarget {
+    service: Service,
+    addresses: Vec<IpAddr>
+}
+
+impl ServiceTarget {
+    pub fn service(&self) -> &Service {
+        &self.service
+    }
+
+    pub fn addresses(&self) -> &[IpAddr] {
+        &self.addresses
+    }
+
+    /// The addresses of the target combined with the port of the service
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.addresses.iter()
+            .map(|address| SocketAddr::new(*address, self.service.port()))
+            .collect()
+    }
+}
+
+pub struct Resolver {
+    requester: Requester,
+    hosts: Option<Hosts>
+}
+
+impl Resolver {
+    pub fn new(config: ResolverConfig) -> Self {
+        let hosts = config.hosts_file().map(Hosts::new);
+
+        Self {
+            requester: Requester::new(config),
+            hosts
+        }
+    }
+
+    /// Builds a resolver configured like the system one, from `/etc/resolv.conf`.
+    pub fn from_resolv_conf() -> Result<Self, DNSError> {
+        Ok(Self::new(ResolverConfig::from_resolv_conf()?))
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        self.requester.config()
+    }
+
+    /// Number of replies dropped so far because they came from an address other
+    /// than the name server asked, didn't match the ID or question of the query,
+    /// or couldn't be decoded.
+    pub fn spoofed_replies(&self) -> usize {
+        self.requester.spoofed_replies()
+    }
+
+    pub fn get_ipv4_address(&self, name: &str) -> Result<Vec<Ipv4Addr>, DNSError> {
+        if let Some(hosts) = &self.hosts {
+            let addresses = hosts.lookup_ipv4(name);
+            if !addresses.is_empty() {
+                return Ok(addresses);
+            }
+        }
+
+        self.lookup(name, Type::A, |data| match data {
+            ResponseData::A(ip) => Some(ip),
+            _ => None
+        })
+    }
+
+    pub fn get_ipv6_address(&self, name: &str) -> Result<Vec<Ipv6Addr>, DNSError> {
+        if let Some(hosts) = &self.hosts {
+            let addresses = hosts.lookup_ipv6(name);
+            if !addresses.is_empty() {
+                return Ok(addresses);
+            }
+        }
+
+        self.lookup(name, Type::AAAA, |data| match data {
+            ResponseData::AAAA(ip) => Some(ip),
+            _ => None
+        })
+    }
+
+    /// Returns the addresses of both families allowed by the configured
+    /// [`IpLookupPolicy`], in the order it prefers. A failure of one family is
+    /// only reported if the other one fails as well.
+    pub fn lookup_ip(&self, name: &str) -> Result<Vec<IpAddr>, DNSError> {
+        let ipv4 = || self.get_ipv4_address(name)
+            .map(|addresses| addresses.into_iter().map(IpAddr::V4).collect::<Vec<_>>());
+        let ipv6 = || self.get_ipv6_address(name)
+            .map(|addresses| addresses.into_iter().map(IpAddr::V6).collect::<Vec<_>>());
+
+        let (first, second) = match self.config().ip_lookup_policy() {
+            IpLookupPolicy::Ipv4Only => return ipv4(),
+            IpLookupPolicy::Ipv6Only => return ipv6(),
+            IpLookupPolicy::Ipv4ThenIpv6 => (ipv4(), ipv6()),
+            IpLookupPolicy::Ipv6ThenIpv4 => (ipv6(), ipv4())
+        };
+
+        match (first, second) {
+            (Ok(mut first), Ok(second)) => {
+                first.extend(second);
+                Ok(first)
+            },
+            (Ok(addresses), Err(_)) | (Err(_), Ok(addresses)) => Ok(addresses),
+            (Err(e), Err(_)) => Err(e)
+        }
+    }
+
+    /// Returns the names of `address`, from the hosts file if it lists it and
+    /// from its PTR records otherwise.
+    pub fn reverse_lookup(&self, address: IpAddr) -> Result<Vec<String>, DNSError> {
+        if let Some(hosts) = &self.hosts {
+            let names = hosts.reverse_lookup(address);
+            if !names.is_empty() {
+                return Ok(names);
+            }
+        }
+
+        self.lookup(&reverse_name(address), Type::PTR, |data| match data {
+            // Without the trailing dot, like the names found in the hosts file
+            ResponseData::PTR(name) => Some(name.to_relative().to_string()),
+            _ => None
+        })
+    }
+
+    /// Returns the mail exchanges of `name`, most preferred first.
+    pub fn lookup_mx(&self, name: &str) -> Result<Vec<MailExchange>, DNSError> {
+        let mut exchanges = self.lookup(name, Type::MailExchange, |data| match data {
+            ResponseData::MailExchange(mx) => Some(mx),
+            _ => None
+        })?;
+        exchanges.sort_by_key(|mx| mx.preference());
+
+        Ok(exchanges)
+    }
+
+    /// Returns the TXT records of `name`.
+    pub fn lookup_txt(&self, name: &str) -> Result<Vec<Text>, DNSError> {
+        self.lookup(name, Type::TXT, |data| match data {
+            ResponseData::TXT(text) => Some(text),
+            _ => None
+        })
+    }
+
+    /// Returns the hosts providing a service, e.g. "_http._tcp.example.com", in
+    /// the order they should be tried: by priority, and then shuffled according
+    /// to their weights (see RFC 2782). The addresses of each target are taken
+    /// from the additional section of the response when the server includes
+    /// them, and looked up otherwise.
+    pub fn lookup_srv(&self, name: &str) -> Result<Vec<ServiceTarget>, DNSError> {
+        let targets = self.lookup_with(name, Type::SRV, |msg, canonical_name| {
+            let services = msg.answers().iter()
+                .filter(|rr| rr.header().name() == canonical_name)
+                .filter_map(|rr| match rr.data() {
+                    ResponseData::SRV(service) => Some(service),
+                    _ => None
+                });
+
+            order_services(services.collect()).into_iter()
+                .map(|service| {
+                    let addresses = msg.additional().iter()
+                        .filter(|rr| rr.header().name() == service.target())
+                        .filter_map(|rr| match rr.data() {
+                            ResponseData::A(ip) => Some(IpAddr::V4(ip)),
+                            ResponseData::AAAA(ip) => Some(IpAddr::V6(ip)),
+                            _ => None
+                        })
+                        .collect();
+
+                    ServiceTarget {
+                        service,
+                        addresses
+                    }
+                })
+                .collect()
+        })?;
+
+        // A single target "." means the service is not available at all
+        if let [target] = targets.as_slice() {
+            if target.service.target().is_root() {
+                return Ok(vec![]);
+            }
+        }
+
+        Ok(targets.into_iter()
+            .map(|mut target| {
+                if target.addresses.is_empty() {
+                    // An absolute name, the target must not go through the search domains
+                    let target_name = target.service.target().to_fqdn().to_string();
+                    target.addresses = self.lookup_ip(&target_name).unwrap_or_default();
+                }
+                target
+            })
+            .collect())
+    }
+
+    /// Returns the authoritative name servers of the zone `name`.
+    pub fn lookup_ns(&self, name: &str) -> Result<Vec<DomainName>, DNSError> {
+        self.lookup(name, Type::NameServer, |data| match data {
+            ResponseData::NameServer(name) => Some(name),
+            _ => None
+        })
+    }
+
+    /// Returns the SOA record of the zone `name`, None if `name` is not the
+    /// apex of a zone.
+    pub fn lookup_soa(&self, name: &str) -> Result<Option<StartOfAuthority>, DNSError> {
+        let soa = self.lookup(name, Type::SOA, |data| match data {
+            ResponseData::SOA(soa) => Some(soa),
+            _ => None
+        })?;
+
+        Ok(soa.into_iter().next())
+    }
+
+    /// Queries the candidate names of `name` for records of type `qtype`, and
+    /// returns what `extract` takes out of the answers for the first candidate
+    /// that has any. The next candidate is only tried if the current one doesn't
+    /// exist or has no such records.
+    fn lookup<T>(&self, name: &str, qtype: Type, extract: impl Fn(ResponseData) -> Option<T>)
+        -> Result<Vec<T>, DNSError> {
+        self.lookup_with(name, qtype, |msg, canonical_name| {
+            msg.answers().iter()
+                .filter(|rr| rr.header().name() == canonical_name)
+                .filter_map(|rr| extract(rr.data()))
+                .collect()
+        })
+    }
+
+    /// Same as [`Resolver::lookup`], but `collect` gets the whole response along
+    /// with the name the CNAME chain (if any) ends at, for lookups that need
+    /// more than the answer section.
+    fn lookup_with<T>(&self, name: &str, qtype: Type, collect: impl Fn(&Message, &DomainName) -> Vec<T>)
+        -> Result<Vec<T>, DNSError> {
+        let mut result = Ok(vec![]);
+        // Whether a candidate exists but has no such records, as the name then
+        // exists even if the candidates tried after it don't
+        let mut got_nodata = false;
+
+        for candidate in self.config().candidate_names(name) {
+            result = self.query_records(&candidate, qtype, &collect);
+
+            match &result {
+                Ok(records) if records.is_empty() => got_nodata = true,
+                Err(DNSError::NonExistentDomain) => continue,
+                _ => break
+            }
+        }
+
+        match result {
+            Err(DNSError::NonExistentDomain) if got_nodata => Ok(vec![]),
+            result => result
+        }
+    }
+
+    fn query_records<T>(&self, name: &str, qtype: Type, collect: impl Fn(&Message, &DomainName) -> Vec<T>)
+        -> Result<Vec<T>, DNSError> {
+        // Candidates are always tried as they are, never through the search domains
+        let query_name = name.parse::<DomainName>()?.to_fqdn();
+
+        let msg = self.query(&query_name, qtype)?;
+
+        if let Some(e) = DNSError::from_response_code(msg.response_code()) {
+            return Err(e);
+        }
+
+        let canonical_name = msg.canonical_name(&query_name);
+
+        Ok(collect(&msg, &canonical_name))
+    }
+
+    /// Sends a query for `name`, with EDNS if enabled. Servers that don't
+    /// implement EDNS answer with FORMERR, NOTIMP or BADVERS; in that case the
+    /// query is repeated as a plain RFC 1035 one.
+    fn query(&self, name: &DomainName, qtype: Type) -> Result<Message, DNSError> {
+        let query = Message::query(name.clone(), qtype, Class::Internet);
+
+        let edns = match self.config().edns() {
+            Some(edns) => edns,
+            None => return self.requester.send_query(&query)
+        };
+
+        let msg = self.requester.send_query(&query.with_edns(edns)?)?;
+
+        let rejects_edns = match msg.response_code() {
+            ResponseCode::BadVersion => true,
+            ResponseCode::FormatError | ResponseCode::NotImplementedError => msg.edns().is_none(),
+            _ => false
+        };
+
+        if rejects_edns {
+            let query = Message::query(name.clone(), qtype, Class::Internet);
+            return self.requester.send_query(&query);
+        }
+
+        Ok(msg)
+    }
+}
+
+/// Sorts services in the order they should be tried, following the algorithm of
+/// RFC 2782: ascending priority and, among the services of the same priority, a
+/// random order where each one has a chance of being next proportional to its
+/// weight.
+fn order_services(mut services: Vec<Service>) -> Vec<Service> {
+    services.sort_by_key(|service| service.priority());
+
+    let mut ordered = Vec::with_capacity(services.len());
+    for priority_group in services.chunk_by(|a, b| a.priority() == b.priority()) {
+        // Services with weight 0 go first, so that they only get picked when the
+        // random number is 0 and are otherwise left for the end
+        let mut remaining: Vec<&Service> = priority_group.iter().collect();
+        remaining.sort_by_key(|service| service.weight() != 0);
+
+        while !remaining.is_empty() {
+            let total_weight: u32 = remaining.iter().map(|service| service.weight() as u32).sum();
+            let chosen_weight = rand::random::<u32>() % (total_weight + 1);
+
+            let mut running_sum = 0;
+            let chosen = remaining.iter()
+                .position(|service| {
+                    running_sum += service.weight() as u32;
+                    running_sum >= chosen_weight
+                })
+                .unwrap_or(0);
+
+            ordered.push(remaining.remove(chosen).clone());
+        }
+    }
+
+    ordered
+}
+
+/// Returns the name under which the PTR records of `address` are found: the
+/// bytes (or nibbles, for IPv6) of the address in reverse order, under
+/// in-addr.arpa or ip6.arpa. The name ends with a dot so that the search
+/// domains are never appended to it.
+fn reverse_name(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(ip) => {
+            let octets: Vec<String> = ip.octets().iter()
+                .rev()
+                .map(|octet| octet.to_string())
+                .collect();
+
+            format!("{}.in-addr.arpa.", octets.join("."))
+        },
+        IpAddr::V6(ip) => {
+            let nibbles: Vec<String> = ip.octets().iter()
+                .rev()
+                .flat_map(|octet| [octet & 0x0F, octet >> 4])
+                .map(|nibble| format!("{:x}", nibble))
+                .collect();
+
+            format!("{}.ip6.arpa.", nibbles.join("."))
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new(ResolverConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::thread;
+
+    use crate::msg::MessageBuilder;
+
+    use super::*;
+
+    // Answers queries for `www.example.com.` with an SOA record and no records,
+    // i.e. NODATA, and every other name with NXDOMAIN
+    fn fake_upstream() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                let query = Message::from_bytes(&buf[..len]).unwrap();
+                let zone: DomainName = "example.com.".parse().unwrap();
+
+                let response = if query.questions()[0].qname() == &"www.example.com.".parse().unwrap() {
+                    let soa = StartOfAuthority::new("ns1.example.com.".parse().unwrap(),
+                                                    "hostmaster.example.com.".parse().unwrap(),
+                                                    1, 7200, 3600, 1209600, 300);
+                    MessageBuilder::response_to(&query)
+                        .with_authority(zone, Class::Internet, 300, ResponseData::SOA(soa))
+                } else {
+                    MessageBuilder::response_to(&query).with_response_code(ResponseCode::NameError)
+                };
+
+                socket.send_to(&response.build().unwrap().to_bytes(), src).unwrap();
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn nodata_is_not_overridden_by_nxdomain_of_a_later_candidate() {
+        let config = ResolverConfig::new(vec![fake_upstream()])
+            .with_search_domains(vec!["corp.local".to_string()])
+            .without_hosts_file();
+        let resolver = Resolver::new(config);
+
+        assert_eq!(resolver.config().candidate_names("www.example.com"),
+                   ["www.example.com", "www.example.com.corp.local"]);
+        assert_eq!(resolver.get_ipv6_address("www.example.com").unwrap(), Vec::<Ipv6Addr>::new());
+    }
+
+    #[test]
+    fn absolute_names_are_queried_as_they_are() {
+        let config = ResolverConfig::new(vec![fake_upstream()])
+            .with_search_domains(vec!["corp.local".to_string()])
+            .without_hosts_file();
+        let resolver = Resolver::new(config);
+
+        assert_eq!(resolver.config().candidate_names("www.example.com."), ["www.example.com."]);
+        assert_eq!(resolver.config().candidate_names("."), ["."]);
+        // The root zone gets queried, and the fake upstream doesn't know it
+        assert!(matches!(resolver.lookup_ns("."), Err(DNSError::NonExistentDomain)));
+    }
+}
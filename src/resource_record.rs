@@ -1,14 +1,19 @@
-use std::io::Bytes;
-use std::net::Ipv4Addr;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
-use crate::domain_name::DomainName;
-use crate::msg::MessageError;
-use crate::serialize::{Deserialize, DeserializationError, read_i32, read_ipv4, read_u16, Serialize};
+use crate::domain_name::{DomainName, NameCompressor};
+use crate::serialize::{Deserialize, DeserializationError, read_character_string, read_ipv4, read_ipv6, read_slice, read_u16, read_u32, Serialize, serialize_character_string};
 
-#[derive(Copy, Clone)]
-pub(crate) enum Class {
-    Internet = 1,
-    Chaos = 3
+// Classes and types are open-ended: codes this crate doesn't know about are kept
+// as Unknown, so that they can be encoded back unchanged (see RFC 3597)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Class {
+    Internet,
+    Chaos,
+    Hesiod,
+    // Only valid in questions, matches any class
+    Any,
+    Unknown(u16)
 }
 
 impl From<u16> for Class {
@@ -16,58 +21,485 @@ impl From<u16> for Class {
         match value {
             1 => Class::Internet,
             3 => Class::Chaos,
-            _ => panic!("Invalid class code")
+            4 => Class::Hesiod,
+            255 => Class::Any,
+            _ => Class::Unknown(value)
         }
     }
 }
 
-#[derive(Copy, Clone)]
-pub(crate) enum Type {
-    A = 1,
-    NameServer = 2,
-    CName = 5,
-    SOA = 6,
-    WKS = 11,
-    PTR = 12,
-    MailExchange = 15
+impl From<Class> for u16 {
+    fn from(value: Class) -> Self {
+        match value {
+            Class::Internet => 1,
+            Class::Chaos => 3,
+            Class::Hesiod => 4,
+            Class::Any => 255,
+            Class::Unknown(value) => value
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Class::Internet => write!(f, "IN"),
+            Class::Chaos => write!(f, "CH"),
+            Class::Hesiod => write!(f, "HS"),
+            Class::Any => write!(f, "ANY"),
+            Class::Unknown(value) => write!(f, "CLASS{}", value)
+        }
+    }
 }
 
-impl TryFrom<u16> for Type {
-    type Error = MessageError;
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    A,
+    NameServer,
+    CName,
+    SOA,
+    WKS,
+    PTR,
+    HINFO,
+    MINFO,
+    MailExchange,
+    TXT,
+    AAAA,
+    SRV,
+    OPT,
+    // Obsoleted by RFC 7208 in favour of TXT records, but still found in old zones
+    SPF,
+    // Types only valid in questions (see RFC 1035, section 3.2.3, and RFC 1995)
+    IXFR,
+    AXFR,
+    ANY,
+    Unknown(u16)
+}
+
+impl From<u16> for Type {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Type::A,
+            2 => Type::NameServer,
+            5 => Type::CName,
+            6 => Type::SOA,
+            11 => Type::WKS,
+            12 => Type::PTR,
+            13 => Type::HINFO,
+            14 => Type::MINFO,
+            15 => Type::MailExchange,
+            16 => Type::TXT,
+            28 => Type::AAAA,
+            33 => Type::SRV,
+            41 => Type::OPT,
+            99 => Type::SPF,
+            251 => Type::IXFR,
+            252 => Type::AXFR,
+            255 => Type::ANY,
+            _ => Type::Unknown(value)
+        }
+    }
+}
 
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
+impl From<Type> for u16 {
+    fn from(value: Type) -> Self {
         match value {
-            1 => Ok(Type::A),
-            2 => Ok(Type::NameServer),
-            5 => Ok(Type::CName),
-            6 => Ok(Type::SOA),
-            11 => Ok(Type::WKS),
-            12 => Ok(Type::PTR),
-            15 => Ok(Type::MailExchange),
-            _ => Err(MessageError::InvalidMessageType)
+            Type::A => 1,
+            Type::NameServer => 2,
+            Type::CName => 5,
+            Type::SOA => 6,
+            Type::WKS => 11,
+            Type::PTR => 12,
+            Type::HINFO => 13,
+            Type::MINFO => 14,
+            Type::MailExchange => 15,
+            Type::TXT => 16,
+            Type::AAAA => 28,
+            Type::SRV => 33,
+            Type::OPT => 41,
+            Type::SPF => 99,
+            Type::IXFR => 251,
+            Type::AXFR => 252,
+            Type::ANY => 255,
+            Type::Unknown(value) => value
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::A => write!(f, "A"),
+            Type::NameServer => write!(f, "NS"),
+            Type::CName => write!(f, "CNAME"),
+            Type::SOA => write!(f, "SOA"),
+            Type::WKS => write!(f, "WKS"),
+            Type::PTR => write!(f, "PTR"),
+            Type::HINFO => write!(f, "HINFO"),
+            Type::MINFO => write!(f, "MINFO"),
+            Type::MailExchange => write!(f, "MX"),
+            Type::TXT => write!(f, "TXT"),
+            Type::AAAA => write!(f, "AAAA"),
+            Type::SRV => write!(f, "SRV"),
+            Type::OPT => write!(f, "OPT"),
+            Type::SPF => write!(f, "SPF"),
+            Type::IXFR => write!(f, "IXFR"),
+            Type::AXFR => write!(f, "AXFR"),
+            Type::ANY => write!(f, "ANY"),
+            Type::Unknown(value) => write!(f, "TYPE{}", value)
+        }
+    }
+}
+
+/// Data of an MX record: a host willing to act as mail exchange for the owner name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MailExchange {
+    preference: u16,
+    exchange: DomainName
+}
+
+impl MailExchange {
+    pub fn new(preference: u16, exchange: DomainName) -> Self {
+        Self {
+            preference,
+            exchange
+        }
+    }
+
+    /// Lower values are preferred
+    pub fn preference(&self) -> u16 {
+        self.preference
+    }
+
+    pub fn exchange(&self) -> &DomainName {
+        &self.exchange
+    }
+}
+
+/// Data of an SOA record, which marks the start of a zone of authority.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartOfAuthority {
+    mname: DomainName,
+    rname: DomainName,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32
+}
+
+impl StartOfAuthority {
+    pub fn new(mname: DomainName, rname: DomainName, serial: u32, refresh: u32, retry: u32, expire: u32,
+               minimum: u32) -> Self {
+        Self {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum
+        }
+    }
+
+    /// Name server that was the original or primary source of data for the zone
+    pub fn mname(&self) -> &DomainName {
+        &self.mname
+    }
+
+    /// Mailbox of the person responsible for the zone, with the '@' written as a '.'
+    pub fn rname(&self) -> &DomainName {
+        &self.rname
+    }
+
+    /// Version number of the zone, increased whenever it changes
+    pub fn serial(&self) -> u32 {
+        self.serial
+    }
+
+    /// Seconds before secondary servers should check the zone for changes
+    pub fn refresh(&self) -> u32 {
+        self.refresh
+    }
+
+    /// Seconds before a failed refresh should be retried
+    pub fn retry(&self) -> u32 {
+        self.retry
+    }
+
+    /// Seconds after which secondary servers stop answering for the zone if
+    /// they can't refresh it
+    pub fn expire(&self) -> u32 {
+        self.expire
+    }
+
+    /// TTL of negative answers for names of the zone (see RFC 2308)
+    pub fn minimum(&self) -> u32 {
+        self.minimum
+    }
+}
+
+/// Data of a TXT (or SPF) record: one or more character-strings, kept as the raw
+/// bytes found on the wire since they are not required to be text at all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Text {
+    strings: Vec<Vec<u8>>
+}
+
+impl Text {
+    /// Character-strings can't be longer than 255 bytes, so longer strings are
+    /// split in several ones
+    pub fn new(strings: Vec<Vec<u8>>) -> Self {
+        let strings = strings.into_iter()
+            .flat_map(|string| match string.len() {
+                0 => vec![string],
+                _ => string.chunks(u8::MAX as usize).map(|chunk| chunk.to_vec()).collect()
+            })
+            .collect();
+
+        Self {
+            strings
         }
     }
+
+    pub fn strings(&self) -> &[Vec<u8>] {
+        &self.strings
+    }
+
+    /// Concatenates all the character-strings. Values longer than 255 bytes, such
+    /// as DKIM keys, must be split in several strings and are read back this way.
+    pub fn joined(&self) -> Vec<u8> {
+        self.strings.concat()
+    }
+
+    /// The joined character-strings as text, with invalid UTF-8 replaced
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.joined()).into_owned()
+    }
+
+    /// Splits an `attribute=value` payload (see RFC 1464) at the first '=', as
+    /// used by most domain verification records. None if there is no '=' or the
+    /// attribute is empty.
+    pub fn key_value(&self) -> Option<(String, String)> {
+        let text = self.to_string_lossy();
+        let (key, value) = text.split_once('=')?;
+
+        if key.trim().is_empty() {
+            return None;
+        }
+
+        Some((key.trim().to_string(), value.to_string()))
+    }
+
+    /// Parses a list of `tag=value` pairs separated by ';', as used by DKIM and
+    /// DMARC records (e.g. "v=DMARC1; p=reject"). Entries without a '=' are skipped.
+    pub fn tags(&self) -> Vec<(String, String)> {
+        self.to_string_lossy()
+            .split(';')
+            .filter_map(|tag| tag.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .filter(|(key, _)| !key.is_empty())
+            .collect()
+    }
+}
+
+/// Data of an HINFO record: the CPU and operating system of a host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostInformation {
+    cpu: Vec<u8>,
+    os: Vec<u8>
+}
+
+impl HostInformation {
+    /// Both values are character-strings, cut at 255 bytes when encoded
+    pub fn new(cpu: Vec<u8>, os: Vec<u8>) -> Self {
+        Self {
+            cpu,
+            os
+        }
+    }
+
+    pub fn cpu(&self) -> &[u8] {
+        &self.cpu
+    }
+
+    pub fn os(&self) -> &[u8] {
+        &self.os
+    }
+}
+
+/// Data of an MINFO record: the mailboxes related to a mailing list or mailbox.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MailInformation {
+    rmailbx: DomainName,
+    emailbx: DomainName
+}
+
+impl MailInformation {
+    pub fn new(rmailbx: DomainName, emailbx: DomainName) -> Self {
+        Self {
+            rmailbx,
+            emailbx
+        }
+    }
+
+    /// Mailbox responsible for the mailing list or mailbox
+    pub fn rmailbx(&self) -> &DomainName {
+        &self.rmailbx
+    }
+
+    /// Mailbox that receives error messages related to the mailing list or mailbox
+    pub fn emailbx(&self) -> &DomainName {
+        &self.emailbx
+    }
+}
+
+/// Data of an SRV record: a host and port providing a service (see RFC 2782).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Service {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: DomainName
+}
+
+impl Service {
+    pub fn new(priority: u16, weight: u16, port: u16, target: DomainName) -> Self {
+        Self {
+            priority,
+            weight,
+            port,
+            target
+        }
+    }
+
+    /// Clients must try the targets with the lowest priority first
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    /// Relative chance of being picked among the targets with the same priority
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Host providing the service. The root name (".") means the service is
+    /// decidedly not available at this domain.
+    pub fn target(&self) -> &DomainName {
+        &self.target
+    }
 }
 
-pub(crate) enum ResponseData {
+/// The RDATA of a record, decoded according to its type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResponseData {
     A(Ipv4Addr),
-    CName(DomainName)
-    // TODO: implement:
-    // - CNAME
-    // - SOA
-    // - WKS
-    // - PTR
-    // - HINFO
-    // - MINFO
-    // - MX
-    // - TXT
+    AAAA(Ipv6Addr),
+    NameServer(DomainName),
+    CName(DomainName),
+    SOA(StartOfAuthority),
+    PTR(DomainName),
+    HINFO(HostInformation),
+    MINFO(MailInformation),
+    MailExchange(MailExchange),
+    TXT(Text),
+    SRV(Service),
+    SPF(Text),
+    // Any other type, with its RDATA left as found on the wire
+    Unknown { rtype: u16, rdata: Vec<u8> }
+}
+
+impl ResponseData {
+    /// The type of the records holding this kind of data
+    pub fn rr_type(&self) -> Type {
+        match self {
+            ResponseData::A(_) => Type::A,
+            ResponseData::AAAA(_) => Type::AAAA,
+            ResponseData::NameServer(_) => Type::NameServer,
+            ResponseData::CName(_) => Type::CName,
+            ResponseData::SOA(_) => Type::SOA,
+            ResponseData::PTR(_) => Type::PTR,
+            ResponseData::HINFO(_) => Type::HINFO,
+            ResponseData::MINFO(_) => Type::MINFO,
+            ResponseData::MailExchange(_) => Type::MailExchange,
+            ResponseData::TXT(_) => Type::TXT,
+            ResponseData::SRV(_) => Type::SRV,
+            ResponseData::SPF(_) => Type::SPF,
+            ResponseData::Unknown { rtype, .. } => Type::from(*rtype)
+        }
+    }
+}
+
+// Writes a <character-string> in presentation format: quoted, with quotes and
+// backslashes escaped and non-printable bytes written as \DDD
+fn write_character_string(f: &mut fmt::Formatter<'_>, string: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for byte in string {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", *byte as char)?,
+            0x20..=0x7E => write!(f, "{}", *byte as char)?,
+            _ => write!(f, "\\{:03}", byte)?
+        }
+    }
+    write!(f, "\"")
 }
 
+// Presentation format of the RDATA, as found in master files
+impl fmt::Display for ResponseData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseData::A(ip) => write!(f, "{}", ip),
+            ResponseData::AAAA(ip) => write!(f, "{}", ip),
+            ResponseData::NameServer(name)
+            | ResponseData::CName(name)
+            | ResponseData::PTR(name) => write!(f, "{}", name),
+            ResponseData::SOA(soa) => write!(f, "{} {} {} {} {} {} {}",
+                soa.mname, soa.rname, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum),
+            ResponseData::HINFO(hinfo) => {
+                write_character_string(f, &hinfo.cpu)?;
+                write!(f, " ")?;
+                write_character_string(f, &hinfo.os)
+            },
+            ResponseData::MINFO(minfo) => write!(f, "{} {}", minfo.rmailbx, minfo.emailbx),
+            ResponseData::MailExchange(mx) => write!(f, "{} {}", mx.preference, mx.exchange),
+            ResponseData::TXT(text) | ResponseData::SPF(text) => {
+                for (i, string) in text.strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_character_string(f, string)?;
+                }
+                Ok(())
+            },
+            ResponseData::SRV(srv) => write!(f, "{} {} {} {}",
+                srv.priority, srv.weight, srv.port, srv.target),
+            // Generic form for types without a known format (see RFC 3597, section 5)
+            ResponseData::Unknown { rdata, .. } => {
+                write!(f, "\\# {}", rdata.len())?;
+                if !rdata.is_empty() {
+                    write!(f, " ")?;
+                    for byte in rdata {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The fields every resource record starts with: its owner name, type, class,
+/// TTL and the length of its RDATA.
+#[derive(Debug)]
 pub struct ResourceRecordHeader {
     name: DomainName,
     rr_type: Type,
     rr_class: Class,
-    ttl: i32,
+    ttl: u32,
     rdlength: u16,
 }
 
@@ -76,7 +508,7 @@ impl ResourceRecordHeader {
         name: DomainName,
         rr_type: Type,
         rr_class: Class,
-        ttl: i32,
+        ttl: u32,
         rdlength: u16,
     ) -> Self {
         Self {
@@ -88,14 +520,55 @@ impl ResourceRecordHeader {
         }
     }
 
-    pub(crate) fn rr_type(&self) -> Type {
+    pub fn name(&self) -> &DomainName {
+        &self.name
+    }
+
+    pub fn rr_type(&self) -> Type {
         self.rr_type
     }
+
+    pub fn rr_class(&self) -> Class {
+        self.rr_class
+    }
+
+    /// Seconds the record may be cached for. Values with the most significant
+    /// bit set are read as 0 (see RFC 2181, section 8).
+    pub fn ttl(&self) -> u32 {
+        if self.ttl > i32::MAX as u32 { 0 } else { self.ttl }
+    }
+
+    /// Length of the RDATA as found on the wire
+    pub fn rdlength(&self) -> u16 {
+        self.rdlength
+    }
+
+    // The RDLENGTH read from the wire may not match the RDATA written back, since
+    // names in it are no longer compressed, so it is given by the caller
+    fn serialize_with_rdlength(&self, rdlength: u16) -> Vec<u8> {
+        let mut bytes = self.name.serialize();
+        bytes.extend_from_slice(&u16::from(self.rr_type).to_be_bytes());
+        bytes.extend_from_slice(&u16::from(self.rr_class).to_be_bytes());
+        bytes.extend_from_slice(&self.ttl.to_be_bytes());
+        bytes.extend_from_slice(&rdlength.to_be_bytes());
+
+        bytes
+    }
+}
+
+impl ResourceRecordHeader {
+    // Writes every field but RDLENGTH, which is only known once the RDATA is written
+    fn serialize_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.name, bytes);
+        bytes.extend_from_slice(&u16::from(self.rr_type).to_be_bytes());
+        bytes.extend_from_slice(&u16::from(self.rr_class).to_be_bytes());
+        bytes.extend_from_slice(&self.ttl.to_be_bytes());
+    }
 }
 
 impl Serialize for ResourceRecordHeader {
     fn serialize(&self) -> Vec<u8> {
-        todo!()
+        self.serialize_with_rdlength(self.rdlength)
     }
 }
 
@@ -104,20 +577,14 @@ impl Deserialize for ResourceRecordHeader {
         let (mut read_bytes, name) = DomainName::deserialize(bytes, offset)?;
 
         let (off, rr_type) = read_u16(bytes, offset + read_bytes)?;
-        let rr_type = match Type::try_from(rr_type) {
-            Ok(rr_type) => rr_type,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid RR Type, {:?}", rr_type)))
-        };
+        let rr_type = Type::from(rr_type);
         read_bytes += off;
 
         let (off, rr_class) = read_u16(bytes, offset + read_bytes)?;
-        let rr_class = match Class::try_from(rr_class) {
-            Ok(rr_class) => rr_class,
-            Err(_) => return Err(DeserializationError::InvalidData(format!("Invalid RR Type, {:?}", rr_class)))
-        };
+        let rr_class = Class::from(rr_class);
         read_bytes += off;
 
-        let (off, ttl) = read_i32(bytes, offset + read_bytes)?;
+        let (off, ttl) = read_u32(bytes, offset + read_bytes)?;
         read_bytes += off;
 
         let (off, rdlength) = read_u16(bytes, offset + read_bytes)?;
@@ -143,10 +610,56 @@ impl Deserialize for ResourceRecordHeader {
 // - I don't want to specify the associated type error each time I want to use a Box<dyn ResourceRecord>
 // I know this might not be the best solution since these traits may be a bit confusing, but I think
 // this is the best way to keep advancing with the project and don't get stuck with this specific part
-pub(crate) trait ResourceRecord {
+/// A resource record of any type. The data of the record is available as a
+/// [`ResponseData`] through [`ResourceRecord::data`].
+pub trait ResourceRecord {
     fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
         -> Result<(usize, Self), DeserializationError> where Self: Sized;
-    fn serialize() -> Vec<u8> where Self: Sized;
+    fn serialize_rdata(&self) -> Vec<u8>;
+    fn header(&self) -> &ResourceRecordHeader;
+    fn data(&self) -> ResponseData;
+
+    fn serialize(&self) -> Vec<u8> {
+        let rdata = self.serialize_rdata();
+
+        // Records only come from decoded messages or MessageBuilder::build, which
+        // makes sure their RDATA fits in RDLENGTH
+        let mut bytes = self.header().serialize_with_rdlength(rdata.len() as u16);
+        bytes.extend_from_slice(&rdata);
+
+        bytes
+    }
+
+    // Names in the RDATA may only be compressed for the types defined in RFC 1035,
+    // which every implementation knows how to decode (see RFC 3597, section 4).
+    // Those types override this; any other one is written as is.
+    #[doc(hidden)]
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, _compressor: &mut NameCompressor) {
+        bytes.extend_from_slice(&self.serialize_rdata());
+    }
+
+    /// Appends the record to `bytes`, which must hold the message from its first byte
+    #[doc(hidden)]
+    fn serialize_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        self.header().serialize_compressed(bytes, compressor);
+
+        let rdlength_offset = bytes.len();
+        bytes.extend_from_slice(&[0, 0]);
+        self.serialize_rdata_compressed(bytes, compressor);
+
+        // Compression only ever shortens the RDATA, so it still fits in RDLENGTH
+        let rdlength = (bytes.len() - rdlength_offset - 2) as u16;
+        bytes[rdlength_offset..rdlength_offset + 2].copy_from_slice(&rdlength.to_be_bytes());
+    }
+}
+
+impl fmt::Debug for dyn ResourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResourceRecord")
+            .field("header", self.header())
+            .field("data", &self.data())
+            .finish()
+    }
 }
 
 pub(crate) struct AResourceRecord {
@@ -168,11 +681,45 @@ impl ResourceRecord for AResourceRecord {
         -> Result<(usize, Self), DeserializationError> {
         let (off, ip) = read_ipv4(bytes, offset)?;
 
-        Ok((offset + off, Self { header, ip }))
+        Ok((off, Self { header, ip }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.ip.octets().to_vec()
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::A(self.ip)
     }
+}
+
+pub(crate) struct AAAAResourceRecord {
+    header: ResourceRecordHeader,
+    ip: Ipv6Addr
+}
+
+impl ResourceRecord for AAAAResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError> {
+        let (off, ip) = read_ipv6(bytes, offset)?;
 
-    fn serialize() -> Vec<u8> {
-        todo!()
+        Ok((off, Self { header, ip }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.ip.octets().to_vec()
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::AAAA(self.ip)
     }
 }
 
@@ -195,11 +742,431 @@ impl ResourceRecord for CNameResourceRecord {
         }))
     }
 
-    fn serialize() -> Vec<u8>
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.cname.serialize()
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.cname, bytes);
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::CName(self.cname.clone())
+    }
+}
+
+pub(crate) struct NameServerResourceRecord {
+    header: ResourceRecordHeader,
+    nsdname: DomainName
+}
+
+impl ResourceRecord for NameServerResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (off, nsdname) = DomainName::deserialize(bytes, offset)?;
+
+        Ok((off, Self {
+            header,
+            nsdname
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.nsdname.serialize()
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.nsdname, bytes);
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::NameServer(self.nsdname.clone())
+    }
+}
+
+pub(crate) struct SOAResourceRecord {
+    header: ResourceRecordHeader,
+    soa: StartOfAuthority
+}
+
+impl ResourceRecord for SOAResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, mname) = DomainName::deserialize(bytes, offset)?;
+
+        let (off, rname) = DomainName::deserialize(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let mut values = [0u32; 5];
+        for value in values.iter_mut() {
+            let (off, v) = read_u32(bytes, offset + read_bytes)?;
+            read_bytes += off;
+            *value = v;
+        }
+        let [serial, refresh, retry, expire, minimum] = values;
+
+        Ok((read_bytes, Self {
+            header,
+            soa: StartOfAuthority {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        let mut bytes = self.soa.mname.serialize();
+        bytes.extend_from_slice(&self.soa.rname.serialize());
+        for value in [self.soa.serial, self.soa.refresh, self.soa.retry, self.soa.expire, self.soa.minimum] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.soa.mname, bytes);
+        compressor.serialize_name(&self.soa.rname, bytes);
+        for value in [self.soa.serial, self.soa.refresh, self.soa.retry, self.soa.expire, self.soa.minimum] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::SOA(self.soa.clone())
+    }
+}
+
+pub(crate) struct PTRResourceRecord {
+    header: ResourceRecordHeader,
+    ptrdname: DomainName
+}
+
+impl ResourceRecord for PTRResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (off, ptrdname) = DomainName::deserialize(bytes, offset)?;
+
+        Ok((off, Self {
+            header,
+            ptrdname
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.ptrdname.serialize()
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.ptrdname, bytes);
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::PTR(self.ptrdname.clone())
+    }
+}
+
+pub(crate) struct HINFOResourceRecord {
+    header: ResourceRecordHeader,
+    hinfo: HostInformation
+}
+
+impl ResourceRecord for HINFOResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, cpu) = read_character_string(bytes, offset)?;
+
+        let (off, os) = read_character_string(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        Ok((read_bytes, Self {
+            header,
+            hinfo: HostInformation {
+                cpu,
+                os
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        let mut bytes = serialize_character_string(&self.hinfo.cpu);
+        bytes.extend_from_slice(&serialize_character_string(&self.hinfo.os));
+
+        bytes
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::HINFO(self.hinfo.clone())
+    }
+}
+
+pub(crate) struct MINFOResourceRecord {
+    header: ResourceRecordHeader,
+    minfo: MailInformation
+}
+
+impl ResourceRecord for MINFOResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, rmailbx) = DomainName::deserialize(bytes, offset)?;
+
+        let (off, emailbx) = DomainName::deserialize(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        Ok((read_bytes, Self {
+            header,
+            minfo: MailInformation {
+                rmailbx,
+                emailbx
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        let mut bytes = self.minfo.rmailbx.serialize();
+        bytes.extend_from_slice(&self.minfo.emailbx.serialize());
+
+        bytes
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        compressor.serialize_name(&self.minfo.rmailbx, bytes);
+        compressor.serialize_name(&self.minfo.emailbx, bytes);
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::MINFO(self.minfo.clone())
+    }
+}
+
+pub(crate) struct MailExchangeResourceRecord {
+    header: ResourceRecordHeader,
+    mx: MailExchange
+}
+
+impl ResourceRecord for MailExchangeResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, preference) = read_u16(bytes, offset)?;
+
+        let (off, exchange) = DomainName::deserialize(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        Ok((read_bytes, Self {
+            header,
+            mx: MailExchange {
+                preference,
+                exchange
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        let mut bytes = self.mx.preference.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&self.mx.exchange.serialize());
+
+        bytes
+    }
+
+    fn serialize_rdata_compressed(&self, bytes: &mut Vec<u8>, compressor: &mut NameCompressor) {
+        bytes.extend_from_slice(&self.mx.preference.to_be_bytes());
+        compressor.serialize_name(&self.mx.exchange, bytes);
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::MailExchange(self.mx.clone())
+    }
+}
+
+// Used for both TXT and SPF records, which share the same RDATA format
+pub(crate) struct TXTResourceRecord {
+    header: ResourceRecordHeader,
+    text: Text
+}
+
+impl ResourceRecord for TXTResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        // Unlike other types, the only way to know how many character-strings
+        // there are is to keep reading until the whole RDATA is consumed
+        let rdlength = header.rdlength as usize;
+        let mut read_bytes = 0;
+        let mut strings = vec![];
+
+        while read_bytes < rdlength {
+            let (off, string) = read_character_string(bytes, offset + read_bytes)?;
+            read_bytes += off;
+            strings.push(string);
+        }
+
+        Ok((read_bytes, Self {
+            header,
+            text: Text {
+                strings
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.text.strings.iter()
+            .flat_map(|string| serialize_character_string(string))
+            .collect()
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        match self.header.rr_type {
+            Type::SPF => ResponseData::SPF(self.text.clone()),
+            _ => ResponseData::TXT(self.text.clone())
+        }
+    }
+}
+
+pub(crate) struct SRVResourceRecord {
+    header: ResourceRecordHeader,
+    service: Service
+}
+
+impl ResourceRecord for SRVResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
+    where
+        Self: Sized
+    {
+        let (mut read_bytes, priority) = read_u16(bytes, offset)?;
+
+        let (off, weight) = read_u16(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let (off, port) = read_u16(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        let (off, target) = DomainName::deserialize(bytes, offset + read_bytes)?;
+        read_bytes += off;
+
+        Ok((read_bytes, Self {
+            header,
+            service: Service {
+                priority,
+                weight,
+                port,
+                target
+            }
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.service.priority.to_be_bytes());
+        bytes.extend_from_slice(&self.service.weight.to_be_bytes());
+        bytes.extend_from_slice(&self.service.port.to_be_bytes());
+        bytes.extend_from_slice(&self.service.target.serialize());
+
+        bytes
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::SRV(self.service.clone())
+    }
+}
+
+// Holds the RDATA of any type without a specific decoder, either because the
+// type is unknown (e.g. HTTPS or CAA) or just not implemented (e.g. WKS). RFC 3597
+// requires such records to be passed along unchanged, so the RDATA is copied as-is
+// and never searched for compressed names.
+pub(crate) struct UnknownResourceRecord {
+    header: ResourceRecordHeader,
+    rdata: Vec<u8>
+}
+
+impl ResourceRecord for UnknownResourceRecord {
+    fn deserialize(header: ResourceRecordHeader, bytes: &[u8], offset: usize)
+        -> Result<(usize, Self), DeserializationError>
     where
         Self: Sized
     {
-        todo!()
+        let rdata = read_slice(bytes, offset, header.rdlength as usize)?.to_vec();
+
+        Ok((rdata.len(), Self {
+            header,
+            rdata
+        }))
+    }
+
+    fn serialize_rdata(&self) -> Vec<u8> {
+        self.rdata.clone()
+    }
+
+    fn header(&self) -> &ResourceRecordHeader {
+        &self.header
+    }
+
+    fn data(&self) -> ResponseData {
+        ResponseData::Unknown {
+            rtype: u16::from(self.header.rr_type),
+            rdata: self.rdata.clone()
+        }
     }
 }
 
@@ -213,11 +1180,74 @@ impl ResourceRecordFactory {
                 let (off, rr) = AResourceRecord::deserialize(header, bytes, offset)?;
                 Ok((off, Box::new(rr)))
             },
+            Type::AAAA => {
+                let (off, rr) = AAAAResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::NameServer => {
+                let (off, rr) = NameServerResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
             Type::CName => {
                 let (off, rr) = CNameResourceRecord::deserialize(header, bytes, offset)?;
                 Ok((off, Box::new(rr)))
             },
-            _ => todo!()
+            Type::SOA => {
+                let (off, rr) = SOAResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::PTR => {
+                let (off, rr) = PTRResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::HINFO => {
+                let (off, rr) = HINFOResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::MINFO => {
+                let (off, rr) = MINFOResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::MailExchange => {
+                let (off, rr) = MailExchangeResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::TXT | Type::SPF => {
+                let (off, rr) = TXTResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            Type::SRV => {
+                let (off, rr) = SRVResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            },
+            _ => {
+                let (off, rr) = UnknownResourceRecord::deserialize(header, bytes, offset)?;
+                Ok((off, Box::new(rr)))
+            }
         }
     }
+
+    /// Builds a record holding `data`, e.g. to put together a response
+    pub(crate) fn from_data(name: DomainName, rr_class: Class, ttl: u32, data: ResponseData)
+        -> Box<dyn ResourceRecord> {
+        // RDLENGTH is worked out from the RDATA when the record is serialized
+        let header = ResourceRecordHeader::new(name, data.rr_type(), rr_class, ttl, 0);
+
+        let rr: Box<dyn ResourceRecord> = match data {
+            ResponseData::A(ip) => Box::new(AResourceRecord::new(header, ip)),
+            ResponseData::AAAA(ip) => Box::new(AAAAResourceRecord { header, ip }),
+            ResponseData::NameServer(nsdname) => Box::new(NameServerResourceRecord { header, nsdname }),
+            ResponseData::CName(cname) => Box::new(CNameResourceRecord { header, cname }),
+            ResponseData::SOA(soa) => Box::new(SOAResourceRecord { header, soa }),
+            ResponseData::PTR(ptrdname) => Box::new(PTRResourceRecord { header, ptrdname }),
+            ResponseData::HINFO(hinfo) => Box::new(HINFOResourceRecord { header, hinfo }),
+            ResponseData::MINFO(minfo) => Box::new(MINFOResourceRecord { header, minfo }),
+            ResponseData::MailExchange(mx) => Box::new(MailExchangeResourceRecord { header, mx }),
+            ResponseData::TXT(text) | ResponseData::SPF(text) => Box::new(TXTResourceRecord { header, text }),
+            ResponseData::SRV(service) => Box::new(SRVResourceRecord { header, service }),
+            ResponseData::Unknown { rdata, .. } => Box::new(UnknownResourceRecord { header, rdata })
+        };
+
+        rr
+    }
 }
\ No newline at end of file
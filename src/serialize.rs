@@ -1,9 +1,59 @@
-use std::net::Ipv4Addr;
+use std::{error, fmt, io};
+use std::net::{Ipv4Addr, Ipv6Addr};
 
+/// Why bytes couldn't be decoded. Offsets are counted from the start of the buffer
+/// being decoded, i.e. usually the message.
+#[derive(Debug)]
 pub enum DeserializationError {
-    BufferOverflow,
-    InvalidData(String),
-    Io(std::io::Error)
+    /// The buffer ends before the `length` bytes starting at `offset`
+    BufferOverflow {
+        offset: usize,
+        length: usize
+    },
+    /// The field starting at `offset` holds a value that is not allowed there
+    InvalidData {
+        offset: usize,
+        field: &'static str,
+        value: u64
+    },
+    Io(io::Error)
+}
+
+impl DeserializationError {
+    pub(crate) fn invalid_data(offset: usize, field: &'static str, value: u64) -> Self {
+        Self::InvalidData {
+            offset,
+            field,
+            value
+        }
+    }
+}
+
+impl fmt::Display for DeserializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserializationError::BufferOverflow { offset, length } =>
+                write!(f, "buffer too short to read {} bytes at offset {}", length, offset),
+            DeserializationError::InvalidData { offset, field, value } =>
+                write!(f, "invalid {} {} at offset {}", field, value, offset),
+            DeserializationError::Io(e) => write!(f, "I/O error: {}", e)
+        }
+    }
+}
+
+impl error::Error for DeserializationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DeserializationError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for DeserializationError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
 }
 
 pub(crate) trait Serialize {
@@ -15,39 +65,66 @@ pub(crate) trait Deserialize {
         -> Result<(usize, Self), DeserializationError> where Self: Sized;
 }
 
+// Returns the `N` bytes starting at `offset`, or an error if the buffer ends before
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], DeserializationError> {
+    offset.checked_add(N)
+        .and_then(|end| bytes.get(offset..end))
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(DeserializationError::BufferOverflow { offset, length: N })
+}
+
 pub(crate) fn read_ipv4(bytes: &[u8], offset: usize)
     -> Result<(usize, Ipv4Addr), DeserializationError> {
-    if offset + 3 > bytes.len() {
-        return Err(DeserializationError::BufferOverflow)
-    }
+    Ok((4, Ipv4Addr::from(read_array::<4>(bytes, offset)?)))
+}
 
-    let bytes: [u8; 4] =
-        bytes[offset..offset + 4].try_into().expect("Couldn't convert bytes into Ipv4");
+pub(crate) fn read_ipv6(bytes: &[u8], offset: usize)
+    -> Result<(usize, Ipv6Addr), DeserializationError> {
+    Ok((16, Ipv6Addr::from(read_array::<16>(bytes, offset)?)))
+}
 
-    Ok((4, Ipv4Addr::from(bytes)))
+pub(crate) fn read_u8(bytes: &[u8], offset: usize)
+    -> Result<(usize, u8), DeserializationError> {
+    Ok((1, u8::from_be_bytes(read_array(bytes, offset)?)))
 }
 
 pub(crate) fn read_u16(bytes: &[u8], offset: usize)
     -> Result<(usize, u16), DeserializationError> {
-    if offset + 1 > bytes.len() {
-        return Err(DeserializationError::BufferOverflow)
-    }
+    Ok((2, u16::from_be_bytes(read_array(bytes, offset)?)))
+}
 
-    Ok((2, u16::from_be_bytes([
-        bytes[offset],
-        bytes[offset + 1]])))
+pub(crate) fn read_u32(bytes: &[u8], offset: usize)
+    -> Result<(usize, u32), DeserializationError> {
+    Ok((4, u32::from_be_bytes(read_array(bytes, offset)?)))
 }
 
-pub(crate) fn read_i32(bytes: &[u8], offset: usize)
-    -> Result<(usize, i32), DeserializationError> {
-    if offset + 3 > bytes.len() {
-        return Err(DeserializationError::BufferOverflow)
-    }
+// A <character-string> is a length octet followed by that many octets
+// (see RFC 1035, section 3.3)
+pub(crate) fn read_character_string(bytes: &[u8], offset: usize)
+    -> Result<(usize, Vec<u8>), DeserializationError> {
+    let (_, length) = read_u8(bytes, offset)?;
+
+    let string = read_slice(bytes, offset + 1, length as usize)?;
 
-    Ok((4, i32::from_be_bytes([
-        bytes[offset],
-        bytes[offset + 1],
-        bytes[offset + 2],
-        bytes[offset + 3],
-    ])))
-}
\ No newline at end of file
+    Ok((string.len() + 1, string.to_vec()))
+}
+
+/// Returns the `length` bytes starting at `offset`, or an error if the buffer ends before
+pub(crate) fn read_slice(bytes: &[u8], offset: usize, length: usize)
+    -> Result<&[u8], DeserializationError> {
+    offset.checked_add(length)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(DeserializationError::BufferOverflow { offset, length })
+}
+
+// Character-strings longer than 255 octets can't be encoded, so callers must
+// split them beforehand
+pub(crate) fn serialize_character_string(string: &[u8]) -> Vec<u8> {
+    let length = string.len().min(u8::MAX as usize);
+
+    let mut bytes = Vec::with_capacity(length + 1);
+    bytes.push(length as u8);
+    bytes.extend_from_slice(&string[..length]);
+
+    bytes
+}